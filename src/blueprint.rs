@@ -1,147 +1,205 @@
 //! Internal workings of the library. You probably don't need to look here.
 
-use std::fmt::Display;
+use std::{fmt::Display, io::Read, sync::Arc};
 
-use ahash::AHashMap;
+use ahash::{AHashMap, AHashSet};
 use kdl::{KdlDocument, KdlNode};
 use miette::{Diagnostic, LabeledSpan, NamedSource, Severity, SourceCode, SourceSpan};
 use smol_str::SmolStr;
 
 use thiserror::Error;
 
+use crate::FabricatorStats;
+
 /// Raw instructions for instantiating an entity, as loaded from disc.
 pub struct RawBlueprint {
     name: SmolStr,
     merge: MergeMode,
+    scope: SpliceScope,
+    when: Option<FlagCondition>,
+    /// Which version of the component formats this was written for.
+    schema: u32,
+    /// Name of the file this was loaded from.
+    filename: SmolStr,
+    /// The text this was loaded from, for pointing warnings at. It's dropped when the blueprint is
+    /// inserted, so the library doesn't keep every file it loaded around.
+    src: Option<Arc<NamedSource>>,
     components: Vec<ComponentEntry>,
 }
 
 impl RawBlueprint {
     pub fn load_from_kdl(
         doc: &KdlDocument,
-        src: NamedSource,
+        src: impl Into<Arc<NamedSource>>,
     ) -> Result<Vec<RawBlueprint>, RawBlueprintDeserError> {
+        let src = src.into();
         let mut out = Vec::new();
         for kid in doc.nodes() {
-            let comps = match kid.children() {
-                Some(comps) => comps,
-                None => {
-                    return Err(RawBlueprintDeserError {
-                        span: *kid.span(),
-                        kind: RawBlueprintParseErrorKind::NoChildren,
-                        src,
-                    })
-                }
+            match RawBlueprint::from_node(kid, &src) {
+                Ok(bp) => out.push(bp),
+                Err((span, kind)) => return Err(RawBlueprintDeserError { span, kind, src }),
+            }
+        }
+
+        Ok(out)
+    }
+
+    /// Load one top-level node. Errors are returned without the source so the caller can attach it.
+    fn from_node(
+        kid: &KdlNode,
+        src: &Arc<NamedSource>,
+    ) -> Result<RawBlueprint, (SourceSpan, RawBlueprintParseErrorKind)> {
+        if let Some(kind) = check_reserved(kid) {
+            return Err((*kid.name().span(), kind));
+        }
+        let comps = match kid.children() {
+            Some(comps) => comps,
+            None => return Err((*kid.span(), RawBlueprintParseErrorKind::NoChildren)),
+        };
+
+        let mut merge = None;
+        let mut scope = None;
+        let mut when = None;
+        let mut schema = None;
+        for entry in kid.entries() {
+            let key = if let Some(key) = entry.name() {
+                key
+            } else {
+                return Err((*entry.span(), RawBlueprintParseErrorKind::TopLevelArgument));
             };
 
-            let mut merge = None;
-            for entry in kid.entries() {
-                let key = if let Some(key) = entry.name() {
-                    key
-                } else {
-                    return Err(RawBlueprintDeserError {
-                        span: *entry.span(),
-                        kind: RawBlueprintParseErrorKind::TopLevelArgument,
-                        src,
-                    });
-                };
+            match key.value() {
+                "merge" => {
+                    if merge.is_some() {
+                        return Err((*entry.span(), RawBlueprintParseErrorKind::ClobberMerge));
+                    }
 
-                match key.value() {
-                    "merge" => {
-                        if merge.is_some() {
-                            return Err(RawBlueprintDeserError {
-                                span: *entry.span(),
-                                kind: RawBlueprintParseErrorKind::ClobberInherit,
-                                src,
-                            });
-                        }
+                    let mode = if let Some(s) = entry.value().as_string() {
+                        s
+                    } else {
+                        return Err((*entry.span(), RawBlueprintParseErrorKind::BadMerge));
+                    };
+                    let mode = match mode.to_lowercase().as_str() {
+                        "merge" => MergeMode::Merge,
+                        "clobber" => MergeMode::Clobber,
+                        _ => return Err((*entry.span(), RawBlueprintParseErrorKind::BadMerge)),
+                    };
+                    merge = Some(mode);
+                }
+                "scope" => {
+                    if scope.is_some() {
+                        return Err((*entry.span(), RawBlueprintParseErrorKind::ClobberScope));
+                    }
 
-                        let mode = if let Some(s) = entry.value().as_string() {
-                            s
-                        } else {
-                            return Err(RawBlueprintDeserError {
-                                span: *entry.span(),
-                                kind: RawBlueprintParseErrorKind::BadMerge,
-                                src,
-                            });
-                        };
-                        let mode = match mode.to_lowercase().as_str() {
-                            "merge" => MergeMode::Merge,
-                            "clobber" => MergeMode::Clobber,
-                            _ => {
-                                return Err(RawBlueprintDeserError {
-                                    span: *entry.span(),
-                                    kind: RawBlueprintParseErrorKind::BadMerge,
-                                    src,
-                                })
-                            }
-                        };
-                        merge = Some(mode);
+                    let new_scope = match entry.value().as_string() {
+                        Some(s) => match s.to_lowercase().as_str() {
+                            "global" => Some(SpliceScope::Global),
+                            "file" => Some(SpliceScope::File),
+                            _ => None,
+                        },
+                        None => None,
+                    };
+                    match new_scope {
+                        Some(it) => scope = Some(it),
+                        None => return Err((*entry.span(), RawBlueprintParseErrorKind::BadScope)),
+                    }
+                }
+                "when" => {
+                    if when.is_some() {
+                        return Err((*entry.span(), RawBlueprintParseErrorKind::ClobberWhen));
                     }
-                    _ => {
-                        return Err(RawBlueprintDeserError {
-                            span: *entry.span(),
-                            kind: RawBlueprintParseErrorKind::InvalidKey,
-                            src,
-                        })
+
+                    match entry.value().as_string().and_then(FlagCondition::parse) {
+                        Some(it) => when = Some(it),
+                        None => return Err((*entry.span(), RawBlueprintParseErrorKind::BadWhen)),
                     }
                 }
+                "schema" => {
+                    if schema.is_some() {
+                        return Err((*entry.span(), RawBlueprintParseErrorKind::ClobberSchema));
+                    }
 
-                // We check down here because it's the "least important" error
-                if entry.ty().is_some() {
-                    return Err(RawBlueprintDeserError {
-                        span: *entry.span(),
-                        kind: RawBlueprintParseErrorKind::TopLevelAnnotation,
-                        src,
-                    });
+                    match entry.value().as_i64().and_then(|it| u32::try_from(it).ok()) {
+                        Some(it) => schema = Some(it),
+                        None => return Err((*entry.span(), RawBlueprintParseErrorKind::BadSchema)),
+                    }
                 }
+                _ => return Err((*entry.span(), RawBlueprintParseErrorKind::InvalidKey)),
             }
-            let merge = merge.unwrap_or_default();
-
-            // We can't use .map here for borrowck reasons
-            let components = {
-                let mut components = Vec::new();
-                for node in comps.nodes() {
-                    let entry = match node.ty() {
-                        None => ComponentEntry::Component(node.clone()),
-                        Some(ann) => {
-                            if !node.entries().is_empty() || node.children().is_some() {
-                                return Err(RawBlueprintDeserError {
-                                    span: *node.span(),
-                                    kind: RawBlueprintParseErrorKind::BadAnnotation,
-                                    src,
-                                });
-                            } else {
-                                match ann.value() {
-                                    "splice" => ComponentEntry::Splice(node.name().value().into()),
-                                    _ => {
-                                        return Err(RawBlueprintDeserError {
-                                            span: *node.span(),
-                                            kind: RawBlueprintParseErrorKind::BadAnnotation,
-                                            src,
-                                        })
-                                    }
+
+            // We check down here because it's the "least important" error
+            if entry.ty().is_some() {
+                return Err((*entry.span(), RawBlueprintParseErrorKind::TopLevelAnnotation));
+            }
+        }
+        let merge = merge.unwrap_or_default();
+        let scope = scope.unwrap_or_default();
+        let schema = schema.unwrap_or(DEFAULT_SCHEMA);
+
+        // We can't use .map here for borrowck reasons
+        let components = {
+            let mut components = Vec::new();
+            for node in comps.nodes() {
+                let entry = match node.ty() {
+                    None => {
+                        if let Some(kind) = check_reserved(node) {
+                            return Err((*node.name().span(), kind));
+                        }
+                        ComponentEntry::Component(node.clone())
+                    }
+                    Some(ann) => {
+                        if !node.entries().is_empty() || node.children().is_some() {
+                            return Err((*node.span(), RawBlueprintParseErrorKind::BadAnnotation));
+                        } else {
+                            match ann.value() {
+                                "splice" => ComponentEntry::Splice(node.name().value().into()),
+                                _ => {
+                                    let kind = RawBlueprintParseErrorKind::BadAnnotation;
+                                    return Err((*node.span(), kind));
                                 }
                             }
                         }
-                    };
-                    components.push(entry);
-                }
-                components
-            };
+                    }
+                };
+                components.push(entry);
+            }
+            components
+        };
 
-            let bp = RawBlueprint {
-                name: kid.name().value().into(),
-                merge,
-                components,
-            };
-            out.push(bp)
-        }
+        let bp = RawBlueprint {
+            name: kid.name().value().into(),
+            merge,
+            scope,
+            when,
+            schema,
+            filename: src.name().into(),
+            src: Some(src.clone()),
+            components,
+        };
+        Ok(bp)
+    }
 
-        Ok(out)
+    /// The name of the blueprint.
+    pub fn name(&self) -> &str {
+        &self.name
     }
 }
 
+/// Names that can't be used for blueprints or (unannotated) components, because they look too much
+/// like blueprint syntax.
+///
+/// - `splice`: a component called `splice` is almost certainly a `(splice)` missing its parens.
+/// - `inherit`: this was how blueprints used to pull in other blueprints.
+pub const RESERVED_NAMES: &[&str] = &["splice", "inherit"];
+
+fn check_reserved(node: &KdlNode) -> Option<RawBlueprintParseErrorKind> {
+    let name = node.name().value();
+    RESERVED_NAMES
+        .contains(&name)
+        .then(|| RawBlueprintParseErrorKind::ReservedName(name.into()))
+}
+
+#[allow(clippy::large_enum_variant)]
 enum ComponentEntry {
     Component(KdlNode),
     Splice(SmolStr),
@@ -149,23 +207,53 @@ enum ComponentEntry {
 /// Instructions for instantiating an entity, with all inheritors folded in.
 pub struct Blueprint {
     pub name: SmolStr,
+    /// The components, in the order they get assembled.
+    ///
+    /// This is always the order they're written in the blueprint, with each `(splice)` replaced by
+    /// the spliced blueprint's components (in its own order) right where the splice is.
+    /// So to have a blueprint's own components applied after the inherited ones, splice first;
+    /// to have them applied before, splice last. Splicing the same blueprint more than once in one
+    /// body folds it in each time, but a base reached again through a different splice (say, by
+    /// two blueprints that both splice it) is only folded in the first time.
     pub components: Vec<KdlNode>,
 }
 
+type Migration = Box<dyn Fn(&mut KdlNode) + Send + Sync>;
+
 /// A library of all the blueprints.
 pub struct BlueprintLibrary {
     /// Map blueprint names to their blueprint.
     prints: AHashMap<SmolStr, RawBlueprint>,
+    /// Flags that are turned on, for `when=` conditions.
+    flags: AHashSet<SmolStr>,
+    /// Map component names and the schema version they upgrade from to the function upgrading them.
+    migrations: AHashMap<(SmolStr, u32), Migration>,
+    /// Components that merging appends to instead of clobbering.
+    repeatable: AHashSet<SmolStr>,
+    /// Map deprecated component names to the names that replace them.
+    aliases: AHashMap<SmolStr, SmolStr>,
+    warn_duplicates: bool,
+    warnings: Vec<LoadWarning>,
 }
 
 impl BlueprintLibrary {
     pub fn new() -> Self {
         Self {
             prints: AHashMap::new(),
+            flags: AHashSet::new(),
+            migrations: AHashMap::new(),
+            repeatable: AHashSet::new(),
+            aliases: AHashMap::new(),
+            warn_duplicates: false,
+            warnings: Vec::new(),
         }
     }
 
-    pub fn insert_raw(&mut self, blueprint: RawBlueprint) {
+    pub fn insert_raw(&mut self, mut blueprint: RawBlueprint) {
+        self.migrate(&mut blueprint);
+        if let Some(src) = blueprint.src.take() {
+            self.find_warnings(&blueprint, &src);
+        }
         match self.prints.get_mut(&blueprint.name) {
             None => {
                 self.prints.insert(blueprint.name.clone(), blueprint);
@@ -178,11 +266,18 @@ impl BlueprintLibrary {
                     for comp in blueprint.components.into_iter() {
                         let clobberee = match &comp {
                             ComponentEntry::Splice(_) => None,
+                            ComponentEntry::Component(new_node)
+                                if self.repeatable.contains(new_node.name().value()) =>
+                            {
+                                None
+                            }
                             ComponentEntry::Component(new_node) => {
-                                // we must have no nodes with the same name
+                                // we must have no nodes with the same name.
+                                // Compare the values, because kdl's `==` also compares how
+                                // identifiers were written, so `"foo"` wouldn't match `foo`
                                 old.components.iter_mut().find(|old_comp| {
                                     if let ComponentEntry::Component(it) = old_comp {
-                                        it.name() == new_node.name()
+                                        it.name().value() == new_node.name().value()
                                     } else {
                                         false
                                     }
@@ -200,69 +295,438 @@ impl BlueprintLibrary {
         }
     }
 
+    /// Remove every blueprint from the library.
+    ///
+    /// Flags are left as they are.
+    pub fn clear(&mut self) {
+        self.prints.clear();
+    }
+
+    /// Register a function to upgrade components with the given name from schema version `from`
+    /// to `from + 1`.
+    ///
+    /// Migrations run when blueprints are inserted, so they have to be registered before the
+    /// blueprints they apply to are loaded.
+    pub fn register_migration(
+        &mut self,
+        component: &str,
+        from: u32,
+        migration: impl Fn(&mut KdlNode) + Send + Sync + 'static,
+    ) {
+        self.migrations.insert((component.into(), from), Box::new(migration));
+    }
+
+    /// Throw away all the registered migrations.
+    pub fn clear_migrations(&mut self) {
+        self.migrations.clear();
+    }
+
+    /// Run each of the blueprint's components through every migration from its schema version on,
+    /// oldest first.
+    fn migrate(&self, blueprint: &mut RawBlueprint) {
+        let latest = match self.migrations.keys().map(|(_, from)| *from).max() {
+            Some(it) => it,
+            None => return,
+        };
+        for comp in blueprint.components.iter_mut() {
+            if let ComponentEntry::Component(node) = comp {
+                // Components don't have to change every version, so skip over gaps.
+                // Look the name up fresh each step in case a migration renamed the node
+                for version in blueprint.schema..=latest {
+                    let key = (node.name().value().into(), version);
+                    if let Some(migration) = self.migrations.get(&key) {
+                        migration(node);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Mark a component as repeatable, so merging over a blueprint adds more of it instead of
+    /// replacing the old one.
+    ///
+    /// This happens when blueprints are inserted, so it has to be done before loading them.
+    pub fn set_repeatable(&mut self, component: &str) {
+        self.repeatable.insert(component.into());
+    }
+
+    /// Check if a component is marked as repeatable.
+    pub fn is_repeatable(&self, component: &str) -> bool {
+        self.repeatable.contains(component)
+    }
+
+    /// Turn a flag on or off.
+    pub fn set_flag(&mut self, flag: &str, enabled: bool) {
+        if enabled {
+            self.flags.insert(flag.into());
+        } else {
+            self.flags.remove(flag);
+        }
+    }
+
+    /// Check if a flag is turned on.
+    pub fn flag_enabled(&self, flag: &str) -> bool {
+        self.flags.contains(flag)
+    }
+
+    /// Check if a blueprint's `when=` condition (if it has one) holds.
+    fn enabled(&self, raw: &RawBlueprint) -> bool {
+        match &raw.when {
+            None => true,
+            Some(cond) => self.flag_enabled(&cond.flag) != cond.negated,
+        }
+    }
+
     /// Insert all the nodes from the given src string.
     pub fn load_str(&mut self, src: &str, filename: &str) -> Result<(), BlueprintParseError> {
         let doc = src.parse()?;
         let source = NamedSource::new(filename, src.to_owned());
-        let raws = RawBlueprint::load_from_kdl(&doc, source)?;
-        for raw in raws {
+        self.load_document(&doc, Arc::new(source))?;
+        Ok(())
+    }
+
+    /// Parse a source string, but only turn its top-level nodes into blueprints one at a time as
+    /// the iterator is advanced, without inserting them anywhere.
+    ///
+    /// The KDL itself is still parsed all at once, so syntax errors show up right away. Pass the
+    /// blueprints you want to [`insert_raw`][BlueprintLibrary::insert_raw].
+    pub fn iter_raw_from_str(
+        src: &str,
+        filename: &str,
+    ) -> Result<
+        impl Iterator<Item = Result<RawBlueprint, RawBlueprintDeserError>>,
+        BlueprintParseError,
+    > {
+        let mut doc: KdlDocument = src.parse()?;
+        let nodes = std::mem::take(doc.nodes_mut());
+        // Every error from this source points at the same copy of it
+        let src = Arc::new(NamedSource::new(filename, src.to_owned()));
+        Ok(nodes.into_iter().map(move |kid| {
+            RawBlueprint::from_node(&kid, &src).map_err(|(span, kind)| RawBlueprintDeserError {
+                span,
+                kind,
+                src: src.clone(),
+            })
+        }))
+    }
+
+    /// Insert all the nodes from an already-parsed document.
+    ///
+    /// `src` is only used for error reporting; it should be the text `doc` was parsed from. It can
+    /// be passed as an `Arc<NamedSource>` to share it with errors from elsewhere.
+    pub fn load_document(
+        &mut self,
+        doc: &KdlDocument,
+        src: impl Into<Arc<NamedSource>>,
+    ) -> Result<(), RawBlueprintDeserError> {
+        // Parse everything before inserting anything, so a bad node leaves the library untouched
+        for raw in RawBlueprint::load_from_kdl(doc, src)? {
             self.insert_raw(raw);
         }
 
         Ok(())
     }
 
-    /// Attempt to lookup a blueprint in the library and form it into a `KdlNode`.
-    pub fn lookup(&self, name: &str) -> Result<Blueprint, BlueprintLookupError> {
-        fn recurse(
-            lib: &BlueprintLibrary,
-            name: &SmolStr,
-            path: Vec<SmolStr>,
-        ) -> Result<Vec<KdlNode>, BlueprintLookupError> {
-            let raw = lib.prints.get(name).ok_or_else(|| match path.as_slice() {
-                [] => BlueprintLookupError::BlueprintNotFound(name.clone()),
-                [.., last] => BlueprintLookupError::InheriteeNotFound(last.clone(), name.clone()),
-            })?;
-            let mut out = Vec::new();
+    /// Warn about every deprecated component in a blueprint, and if turned on, every component
+    /// written more than once in it.
+    ///
+    /// This runs on migrated blueprints, so it sees the names the components will be assembled
+    /// with.
+    fn find_warnings(&mut self, raw: &RawBlueprint, src: &Arc<NamedSource>) {
+        let mut last_seen = AHashMap::new();
+        for comp in raw.components.iter() {
+            let node = match comp {
+                ComponentEntry::Component(node) => node,
+                ComponentEntry::Splice(_) => continue,
+            };
+            let name = node.name().value();
+            if let Some(new) = self.aliases.get(name) {
+                self.warnings.push(LoadWarning::DeprecatedComponent(DeprecatedComponentWarning {
+                    blueprint: raw.name.clone(),
+                    component: name.into(),
+                    replacement: new.clone(),
+                    span: *node.name().span(),
+                    src: src.clone(),
+                }));
+            }
+            if !self.warn_duplicates || self.repeatable.contains(name) {
+                continue;
+            }
+            if let Some(previous) = last_seen.insert(name, *node.span()) {
+                self.warnings.push(LoadWarning::DuplicateComponent(DuplicateComponentWarning {
+                    blueprint: raw.name.clone(),
+                    component: name.into(),
+                    previous,
+                    span: *node.span(),
+                    src: src.clone(),
+                }));
+            }
+        }
+    }
+
+    /// Mark `old` as a deprecated name for the component `new`.
+    ///
+    /// This only records the alias, for warning about when loading; it's up to the caller to
+    /// actually use `new`'s assembler for `old`.
+    pub fn set_deprecated_alias(&mut self, old: &str, new: &str) {
+        self.aliases.insert(old.into(), new.into());
+    }
+
+    /// Get what a deprecated component name was replaced with, if it is one.
+    pub fn deprecated_alias(&self, name: &str) -> Option<&SmolStr> {
+        self.aliases.get(name)
+    }
+
+    /// Turn on or off warning about components written twice in the same blueprint, for catching
+    /// copy-paste mistakes. It's off by default.
+    ///
+    /// Warnings are collected as blueprints are inserted; get them with
+    /// [`take_warnings`][BlueprintLibrary::take_warnings].
+    pub fn set_warn_duplicates(&mut self, warn: bool) {
+        self.warn_duplicates = warn;
+    }
+
+    /// Take all the warnings collected while loading so far.
+    pub fn take_warnings(&mut self) -> Vec<LoadWarning> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    /// Insert all the nodes from each of the given `(src, filename)` pairs.
+    ///
+    /// Unlike [`load_str`][BlueprintLibrary::load_str] this doesn't stop at the first bad file;
+    /// every file that can be loaded is, and the errors from the rest are all returned together.
+    pub fn load_strs<'a>(
+        &mut self,
+        srcs: impl IntoIterator<Item = (&'a str, &'a str)>,
+    ) -> Result<(), BlueprintParseErrors> {
+        let errors = srcs
+            .into_iter()
+            .filter_map(|(src, filename)| self.load_str(src, filename).err())
+            .collect::<Vec<_>>();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(BlueprintParseErrors(errors))
+        }
+    }
+
+    /// Insert all the nodes from the given bytes, which must be UTF-8.
+    pub fn load_bytes(&mut self, src: &[u8], filename: &str) -> Result<(), BlueprintParseError> {
+        let src = std::str::from_utf8(src)?;
+        self.load_str(src, filename)
+    }
+
+    /// Read everything out of the reader and insert all the nodes in it.
+    pub fn load_reader(
+        &mut self,
+        mut reader: impl Read,
+        filename: &str,
+    ) -> Result<(), BlueprintParseError> {
+        let mut src = Vec::new();
+        reader.read_to_end(&mut src)?;
+        self.load_bytes(&src, filename)
+    }
+
+    /// Count up how much stuff is in the library.
+    pub fn stats(&self) -> FabricatorStats {
+        let mut stats = FabricatorStats {
+            blueprints: self.prints.len(),
+            ..Default::default()
+        };
+        for (name, raw) in self.prints.iter() {
+            // The key and `raw.name` are the same string, so only count it once
+            stats.approx_memory += std::mem::size_of::<RawBlueprint>() + name.len();
             for comp in raw.components.iter() {
+                stats.approx_memory += std::mem::size_of::<ComponentEntry>();
                 match comp {
                     ComponentEntry::Component(node) => {
-                        out.push(node.clone());
+                        stats.components += 1;
+                        // kdl doesn't tell us how much it allocated, so the length
+                        // of the node's text will have to do
+                        stats.approx_memory += node.to_string().len();
                     }
                     ComponentEntry::Splice(parent_name) => {
-                        // Check for loops
-                        if let Some(ono) = path
-                            .iter()
-                            .enumerate()
-                            .find_map(|(idx, kid)| (kid == parent_name).then_some(idx))
-                        {
-                            let mut problem = path[ono..].to_vec();
-                            // Push the current one ...
-                            problem.push(name.clone());
-                            // and the start of the loop
-                            problem.push(path[ono].clone());
-                            return Err(BlueprintLookupError::InheritanceLoop(problem));
-                        }
-
-                        let mut path2 = path.clone();
-                        path2.push(name.clone());
-                        let to_splice = recurse(lib, parent_name, path2)?;
-
-                        out.extend(to_splice);
+                        stats.approx_memory += parent_name.len();
                     }
                 }
             }
 
-            Ok(out)
+            // Broken blueprints just don't count towards the depth. Flags are ignored, so this only
+            // depends on the content and not on what happens to be turned on
+            let _ = self.walk(name, false, Vec::new(), &mut AHashMap::new(), &mut |found| {
+                if let Walked::Splice(_, depth) = found {
+                    stats.max_inheritance_depth = stats.max_inheritance_depth.max(depth);
+                }
+            });
         }
+        stats
+    }
+
+    /// Iterate over the names of every component node written in any blueprint.
+    ///
+    /// Spliced blueprints live in the library too, so this also covers every component a
+    /// resolved blueprint could have.
+    pub fn component_names(&self) -> impl Iterator<Item = &str> + '_ {
+        self.prints.values().flat_map(|raw| {
+            raw.components.iter().filter_map(|comp| match comp {
+                ComponentEntry::Component(node) => Some(node.name().value()),
+                ComponentEntry::Splice(_) => None,
+            })
+        })
+    }
 
-        let smol_name = name.into();
-        let components = recurse(self, &smol_name, Vec::new())?;
+    /// Attempt to lookup a blueprint in the library and form it into a `KdlNode`.
+    pub fn lookup(&self, name: &str) -> Result<Blueprint, BlueprintLookupError> {
+        let mut components = Vec::new();
+        self.walk(&name.into(), true, Vec::new(), &mut AHashMap::new(), &mut |found| {
+            if let Walked::Component(node) = found {
+                components.push(node.clone());
+            }
+        })?;
         Ok(Blueprint {
             name: name.into(),
             components,
         })
     }
+
+    /// Get the names of all the blueprints spliced into the given one, in the order `lookup`
+    /// folds them in.
+    ///
+    /// Blueprints spliced in by other spliced blueprints are included; the blueprint itself isn't.
+    /// A base reached again through a different splice is only listed the first time.
+    pub fn inheritance_chain(&self, name: &str) -> Result<Vec<SmolStr>, BlueprintLookupError> {
+        let mut chain = Vec::new();
+        self.walk(&name.into(), true, Vec::new(), &mut AHashMap::new(), &mut |found| {
+            if let Walked::Splice(parent_name, _) = found {
+                chain.push(parent_name.clone());
+            }
+        })?;
+        Ok(chain)
+    }
+
+    /// Get the names of all the blueprints that splice in the given one, directly or through
+    /// other splices, sorted by name.
+    ///
+    /// Blueprints that can't be looked up (say, because of a splice loop) are still checked as far
+    /// as lookup gets before failing. `when=` conditions are ignored, so blueprints that are
+    /// turned off right now still count.
+    pub fn descendants_of(&self, name: &str) -> Vec<SmolStr> {
+        let mut out = self
+            .prints
+            .keys()
+            .filter(|child| {
+                let mut found = false;
+                let _ = self.walk(child, false, Vec::new(), &mut AHashMap::new(), &mut |walked| {
+                    if let Walked::Splice(parent_name, _) = walked {
+                        found |= parent_name == name;
+                    }
+                });
+                found
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        out.sort();
+        out
+    }
+
+    /// Walk through a blueprint in resolution order, descending into splices.
+    ///
+    /// `visit` is called with each splice just before the components it brings in.
+    ///
+    /// If `use_flags` is set, blueprints whose `when=` condition doesn't hold are treated like
+    /// they're not there, like lookup does. Otherwise the walk goes by how the blueprints are
+    /// written, for looking at their structure.
+    ///
+    /// `spliced` maps every blueprint spliced in so far to the splice path it was first reached
+    /// by. A blueprint reached again by a different path (say, through both sides of a diamond)
+    /// isn't walked again, so its components land before everything that splices it in. Repeated
+    /// splices along the same path are written out on purpose, so they're all walked.
+    fn walk<'a>(
+        &'a self,
+        name: &SmolStr,
+        use_flags: bool,
+        path: Vec<SmolStr>,
+        spliced: &mut AHashMap<SmolStr, Vec<SmolStr>>,
+        visit: &mut dyn FnMut(Walked<'a>),
+    ) -> Result<(), BlueprintLookupError> {
+        let raw = match self.prints.get(name) {
+            Some(raw) if use_flags && path.is_empty() && !self.enabled(raw) => None,
+            it => it,
+        };
+        let raw = raw.ok_or_else(|| match path.as_slice() {
+            [] => BlueprintLookupError::BlueprintNotFound(name.clone()),
+            [via @ .., referrer] => BlueprintLookupError::InheriteeNotFound(
+                referrer.clone(),
+                name.clone(),
+                via.to_vec(),
+            ),
+        })?;
+        for comp in raw.components.iter() {
+            match comp {
+                ComponentEntry::Component(node) => {
+                    visit(Walked::Component(node));
+                }
+                ComponentEntry::Splice(parent_name) => {
+                    // Check for loops
+                    if let Some(ono) = path
+                        .iter()
+                        .enumerate()
+                        .find_map(|(idx, kid)| (kid == parent_name).then_some(idx))
+                    {
+                        let mut problem = path[ono..].to_vec();
+                        // Push the current one ...
+                        problem.push(name.clone());
+                        // and the start of the loop
+                        problem.push(path[ono].clone());
+                        return Err(BlueprintLookupError::InheritanceLoop(problem));
+                    }
+
+                    if let Some(parent) = self.prints.get(parent_name) {
+                        if raw.scope == SpliceScope::File && parent.filename != raw.filename {
+                            return Err(BlueprintLookupError::InheriteeOutOfScope(
+                                name.clone(),
+                                parent_name.clone(),
+                                path.clone(),
+                            ));
+                        }
+                        // Splicing in a turned-off blueprint does nothing
+                        if use_flags && !self.enabled(parent) {
+                            continue;
+                        }
+                    }
+                    let mut path2 = path.clone();
+                    path2.push(name.clone());
+                    // It's already been folded in by some other blueprint
+                    match spliced.get(parent_name) {
+                        Some(first) if *first != path2 => continue,
+                        Some(_) => {}
+                        None => {
+                            spliced.insert(parent_name.clone(), path2.clone());
+                        }
+                    }
+
+                    visit(Walked::Splice(parent_name, path.len() + 1));
+
+                    self.walk(parent_name, use_flags, path2, spliced, visit)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for BlueprintLibrary {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Something found while walking through a blueprint.
+enum Walked<'a> {
+    Component(&'a KdlNode),
+    /// A spliced blueprint and how many splices deep it is.
+    Splice(&'a SmolStr, usize),
 }
 
 /// How to handle this blueprint if there's another node with the same name.
@@ -282,6 +746,45 @@ pub enum MergeMode {
     Clobber,
 }
 
+/// The schema version of blueprints that don't say what version they are.
+pub const DEFAULT_SCHEMA: u32 = 1;
+
+/// A blueprint's `when=` condition: `when="flag"` turns the blueprint on only when the flag is
+/// on, and `when="!flag"` only when it's off.
+///
+/// A turned-off blueprint acts like it doesn't exist, except that splicing it in is fine and just
+/// adds nothing. Like the merge mode, this can't be changed by merging over a blueprint.
+#[derive(Debug, Clone)]
+struct FlagCondition {
+    flag: SmolStr,
+    negated: bool,
+}
+
+impl FlagCondition {
+    fn parse(s: &str) -> Option<Self> {
+        let (flag, negated) = match s.strip_prefix('!') {
+            Some(flag) => (flag, true),
+            None => (s, false),
+        };
+        (!flag.is_empty()).then(|| FlagCondition {
+            flag: flag.into(),
+            negated,
+        })
+    }
+}
+
+/// Which blueprints a blueprint is allowed to splice in.
+///
+/// Like the merge mode, this can't be changed by merging over a blueprint.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SpliceScope {
+    /// Splice in blueprints from anywhere. This is the default behavior.
+    #[default]
+    Global,
+    /// Only splice in blueprints loaded from the same file as this one.
+    File,
+}
+
 /// Problems when looking up a blueprint.
 #[derive(Debug, Error, PartialEq, Eq)]
 pub enum BlueprintLookupError {
@@ -289,10 +792,26 @@ pub enum BlueprintLookupError {
     BlueprintNotFound(SmolStr),
     #[error("when trying to inherit from another blueprint, the following loop was found: {0:?}")]
     InheritanceLoop(Vec<SmolStr>),
+    /// The last field is the chain of splices leading to the blueprint that tried to splice in the
+    /// missing one, starting with the entrypoint. It's empty if the entrypoint did it.
+    #[error(
+        "the blueprint {0} tried to inherit from the blueprint {1} but the second was not found \
+        (via {})",
+        show_path(.2, .0)
+    )]
+    InheriteeNotFound(SmolStr, SmolStr, Vec<SmolStr>),
+    /// The last field is the chain of splices leading to the blueprint with file scope, starting
+    /// with the entrypoint. It's empty if the entrypoint has file scope.
     #[error(
-        "the blueprint {0} tried to inherit from the blueprint {1} but the second was not found"
+        "the blueprint {0} can only splice in blueprints from its own file, but {1} is from \
+        another (via {})",
+        show_path(.2, .0)
     )]
-    InheriteeNotFound(SmolStr, SmolStr),
+    InheriteeOutOfScope(SmolStr, SmolStr, Vec<SmolStr>),
+}
+
+fn show_path(via: &[SmolStr], last: &SmolStr) -> String {
+    via.iter().chain([last]).map(SmolStr::as_str).collect::<Vec<_>>().join(" -> ")
 }
 
 #[derive(Debug, Error)]
@@ -301,16 +820,22 @@ pub enum BlueprintParseError {
     Parse(#[from] kdl::KdlError),
     #[error("error when turning kdl into blueprints: {0}")]
     Deser(#[from] RawBlueprintDeserError),
+    #[error("error when reading blueprints: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("blueprints were not valid utf-8: {0}")]
+    Utf8(#[from] std::str::Utf8Error),
 }
 
 macro_rules! passthru {
     ($($func:ident -> $ret:ty);*) => {
         $(
+            #[allow(clippy::needless_lifetimes)]
             fn $func<'a>(&'a self) -> $ret {
                 match self {
                     // this can't be done automatically
                     BlueprintParseError::Parse(x) => x.$func(),
                     BlueprintParseError::Deser(x) => x.$func(),
+                    BlueprintParseError::Io(_) | BlueprintParseError::Utf8(_) => None,
                 }
             }
         )*
@@ -323,10 +848,21 @@ impl Diagnostic for BlueprintParseError {
         severity  -> Option<Severity>;
         help -> Option<Box<dyn Display + 'a>>;
         url -> Option<Box<dyn Display + 'a>>;
-        source_code -> Option<&dyn SourceCode>;
-        labels -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>>;
+        source_code -> Option<&'a dyn SourceCode>;
+        labels -> Option<Box<dyn Iterator<Item = LabeledSpan> + 'a>>;
         related -> Option<Box<dyn Iterator<Item = &'a dyn Diagnostic> + 'a>>;
-        diagnostic_source -> Option<&dyn Diagnostic>
+        diagnostic_source -> Option<&'a dyn Diagnostic>
+    }
+}
+
+/// Every error from loading several blueprint files at once.
+#[derive(Debug, Error)]
+#[error("{} blueprint file(s) failed to load", .0.len())]
+pub struct BlueprintParseErrors(pub Vec<BlueprintParseError>);
+
+impl Diagnostic for BlueprintParseErrors {
+    fn related<'a>(&'a self) -> Option<Box<dyn Iterator<Item = &'a dyn Diagnostic> + 'a>> {
+        Some(Box::new(self.0.iter().map(|err| err as &dyn Diagnostic)))
     }
 }
 
@@ -335,12 +871,64 @@ impl Diagnostic for BlueprintParseError {
 pub struct RawBlueprintDeserError {
     #[label]
     pub span: SourceSpan,
+    /// Shared with everything else reported from the same source, so a pile of errors doesn't
+    /// mean a pile of copies of the file.
     #[source_code]
-    pub src: NamedSource,
+    pub src: Arc<NamedSource>,
     pub kind: RawBlueprintParseErrorKind,
 }
 
-const TOP_LEVEL_REQS: &str = r#"only `merge="merge"` or `merge="clobber"` are allowed"#;
+/// Something suspicious, but not wrong enough to fail over, found while loading blueprints.
+#[derive(Debug, Error, Diagnostic)]
+pub enum LoadWarning {
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    DuplicateComponent(DuplicateComponentWarning),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    DeprecatedComponent(DeprecatedComponentWarning),
+}
+
+/// A component used under a deprecated name.
+#[derive(Debug, Error, Diagnostic)]
+#[error("component {component} in blueprint {blueprint} is deprecated")]
+#[diagnostic(severity(Warning), help("use `{replacement}` instead"))]
+pub struct DeprecatedComponentWarning {
+    pub blueprint: SmolStr,
+    pub component: SmolStr,
+    pub replacement: SmolStr,
+    #[label("deprecated name")]
+    pub span: SourceSpan,
+    /// Shared between all the warnings from one file.
+    #[source_code]
+    pub src: Arc<NamedSource>,
+}
+
+/// A component written twice in the same blueprint. Only the later one will end up on the entity.
+///
+/// These are only collected if turned on with
+/// [`set_warn_duplicates`][BlueprintLibrary::set_warn_duplicates].
+#[derive(Debug, Error, Diagnostic)]
+#[error("component {component} is defined twice in blueprint {blueprint}; the later one wins")]
+#[diagnostic(severity(Warning))]
+pub struct DuplicateComponentWarning {
+    pub blueprint: SmolStr,
+    pub component: SmolStr,
+    /// The copy just before this one.
+    #[label("previously defined here")]
+    pub previous: SourceSpan,
+    #[label("defined again here")]
+    pub span: SourceSpan,
+    /// Shared between all the warnings from one file.
+    #[source_code]
+    pub src: Arc<NamedSource>,
+}
+
+const TOP_LEVEL_REQS: &str =
+    concat!(
+        r#"only `merge="merge"|"clobber"`, `scope="global"|"file"`, `when="flag"|"!flag"`, "#,
+        "and `schema=<version>` are allowed"
+    );
 const ANN_REQS: &str =
     r#"only `(splice)a-blueprint` with no further args/props/children is allowed"#;
 
@@ -360,6 +948,20 @@ pub enum RawBlueprintParseErrorKind {
     ClobberInherit,
     #[error("redefined `merge`")]
     ClobberMerge,
+    #[error(r#"the `scope` key didn't equal "global" or "file""#)]
+    BadScope,
+    #[error("redefined `scope`")]
+    ClobberScope,
+    #[error(r#"the `when` key wasn't a flag like "flag" or "!flag""#)]
+    BadWhen,
+    #[error("redefined `when`")]
+    ClobberWhen,
+    #[error("the `schema` key wasn't a non-negative whole number")]
+    BadSchema,
+    #[error("redefined `schema`")]
+    ClobberSchema,
     #[error("bad annotation; {}", ANN_REQS)]
     BadAnnotation,
+    #[error("`{0}` is a reserved name")]
+    ReservedName(SmolStr),
 }
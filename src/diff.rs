@@ -0,0 +1,160 @@
+//! Comparing blueprints, for content tools and editors.
+
+use std::collections::BTreeMap;
+
+use kdl::{KdlDocument, KdlEntry, KdlNode, KdlValue};
+
+use crate::blueprint::Blueprint;
+
+/// What changed between two resolved blueprints.
+#[derive(Debug, Clone, Default)]
+pub struct BlueprintDiff {
+    /// Components only the new blueprint has.
+    pub added: Vec<KdlNode>,
+    /// Components only the old blueprint has.
+    pub removed: Vec<KdlNode>,
+    /// Components both have, but with different contents.
+    pub changed: Vec<ComponentChange>,
+}
+
+impl BlueprintDiff {
+    /// Return true if the two blueprints had the same components.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.changed.is_empty()
+    }
+}
+
+/// A component whose contents differ between two blueprints.
+///
+/// Differences anywhere inside the component, including deep in its
+/// children, show up as one change to the whole component; compare
+/// `old` and `new` to dig into it.
+#[derive(Debug, Clone)]
+pub struct ComponentChange {
+    pub old: KdlNode,
+    pub new: KdlNode,
+}
+
+impl Blueprint {
+    /// Compare this blueprint's components against a newer version.
+    ///
+    /// Components are matched up by name. If there are several components
+    /// with the same name, the first one here is matched with the first one
+    /// there, and so on. Formatting is ignored; see [`nodes_equivalent`].
+    pub fn diff(&self, new: &Blueprint) -> BlueprintDiff {
+        let mut new_by_name = BTreeMap::<&str, Vec<&KdlNode>>::new();
+        for node in new.components.iter() {
+            new_by_name
+                .entry(node.name().value())
+                .or_default()
+                .push(node);
+        }
+
+        let mut diff = BlueprintDiff::default();
+        let mut old_counts = BTreeMap::<&str, usize>::new();
+        for old in self.components.iter() {
+            let name = old.name().value();
+            let idx = old_counts.entry(name).or_default();
+            match new_by_name.get(name).and_then(|news| news.get(*idx)) {
+                Some(new) if !nodes_equivalent(old, new) => {
+                    diff.changed.push(ComponentChange {
+                        old: old.clone(),
+                        new: (*new).clone(),
+                    })
+                }
+                Some(_) => {}
+                None => diff.removed.push(old.clone()),
+            }
+            *idx += 1;
+        }
+
+        // Any more of a name than the old blueprint had are new
+        let mut new_counts = BTreeMap::<&str, usize>::new();
+        for new in new.components.iter() {
+            let name = new.name().value();
+            let idx = new_counts.entry(name).or_default();
+            if *idx >= old_counts.get(name).copied().unwrap_or_default() {
+                diff.added.push(new.clone());
+            }
+            *idx += 1;
+        }
+
+        diff
+    }
+}
+
+/// Check if two nodes mean the same thing, ignoring how they're formatted.
+///
+/// Whitespace and comments don't matter, and neither does how values are
+/// written, so `0x10` equals `16` and `r"foo"` equals `"foo"`. Properties can
+/// be in any order (with the last one winning if a key is repeated), but
+/// arguments and children have to be in the same order.
+pub fn nodes_equivalent(a: &KdlNode, b: &KdlNode) -> bool {
+    a.name().value() == b.name().value()
+        && a.ty().map(|ty| ty.value()) == b.ty().map(|ty| ty.value())
+        && entries_equivalent(a.entries(), b.entries())
+        && children_equivalent(a.children(), b.children())
+}
+
+fn entries_equivalent(a: &[KdlEntry], b: &[KdlEntry]) -> bool {
+    fn split(
+        entries: &[KdlEntry],
+    ) -> (Vec<&KdlEntry>, BTreeMap<&str, &KdlEntry>) {
+        let mut args = Vec::new();
+        let mut props = BTreeMap::new();
+        for entry in entries {
+            match entry.name() {
+                Some(key) => {
+                    props.insert(key.value(), entry);
+                }
+                None => args.push(entry),
+            }
+        }
+        (args, props)
+    }
+
+    let (a_args, a_props) = split(a);
+    let (b_args, b_props) = split(b);
+    a_args.len() == b_args.len()
+        && a_args
+            .iter()
+            .zip(b_args.iter())
+            .all(|(a, b)| entry_equivalent(a, b))
+        && a_props.len() == b_props.len()
+        && a_props.iter().all(|(key, a)| match b_props.get(key) {
+            Some(b) => entry_equivalent(a, b),
+            None => false,
+        })
+}
+
+fn entry_equivalent(a: &KdlEntry, b: &KdlEntry) -> bool {
+    a.ty().map(|ty| ty.value()) == b.ty().map(|ty| ty.value())
+        && values_equivalent(a.value(), b.value())
+}
+
+fn values_equivalent(a: &KdlValue, b: &KdlValue) -> bool {
+    if let (Some(a), Some(b)) = (a.as_string(), b.as_string()) {
+        a == b
+    } else if let (Some(a), Some(b)) = (a.as_i64(), b.as_i64()) {
+        a == b
+    } else if let (Some(a), Some(b)) = (a.as_f64(), b.as_f64()) {
+        a == b
+    } else if let (Some(a), Some(b)) = (a.as_bool(), b.as_bool()) {
+        a == b
+    } else {
+        a.is_null() && b.is_null()
+    }
+}
+
+fn children_equivalent(
+    a: Option<&KdlDocument>,
+    b: Option<&KdlDocument>,
+) -> bool {
+    // No children block is the same as an empty one
+    let a = a.map(|doc| doc.nodes()).unwrap_or_default();
+    let b = b.map(|doc| doc.nodes()).unwrap_or_default();
+    a.len() == b.len()
+        && a.iter().zip(b.iter()).all(|(a, b)| nodes_equivalent(a, b))
+}
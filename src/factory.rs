@@ -1,15 +1,26 @@
 use std::marker::PhantomData;
 
+use ahash::AHashSet;
+use eyre::bail;
+use heck::ToSnakeCase;
 use kdl::KdlNode;
 use palkia::prelude::*;
 use serde::de::DeserializeOwned;
 
+use crate::resource::Resources;
+
 /// Do one step of building an entity from a node. Usually, implementors will:
 /// - Deser a component out of the node
 /// - Add it to the builder
 ///
 /// Each assembler is a singleton object stored in an [`EntityFabricator`].
 /// You can use the `&self` param for configuration data, I suppose.
+///
+/// Palkia hands out the entity's id as soon as it's spawned, before it's built,
+/// so `builder.entity` is already the id the finished entity will have.
+/// Components that need to know which entity they're on can just copy it.
+///
+/// [`EntityFabricator`]: crate::EntityFabricator
 pub trait ComponentFactory<Ctx>: Send + Sync + 'static
 where
     Ctx: 'static,
@@ -21,18 +32,154 @@ where
         node: &KdlNode,
         ctx: &Ctx,
     ) -> eyre::Result<EntityBuilder<'a, 'w>>;
+
+    /// Like [`assemble`][ComponentFactory::assemble], but with mutable access to the context.
+    ///
+    /// By default it just calls `assemble`, so only factories that need to change the context
+    /// have to implement it.
+    fn assemble_mut<'a, 'w>(
+        &self,
+        builder: EntityBuilder<'a, 'w>,
+        node: &KdlNode,
+        ctx: &mut Ctx,
+    ) -> eyre::Result<EntityBuilder<'a, 'w>> {
+        self.assemble(builder, node, ctx)
+    }
+
+    /// Assemble with everything the fabricator hands out: the context, mutably if it was passed
+    /// mutably, and the shared [`Resources`].
+    ///
+    /// This is the only one of these methods [`EntityFabricator`] calls itself. By default it
+    /// calls `assemble_mut` if the context is mutable and `assemble` if it isn't, so only
+    /// factories that need resources have to implement it.
+    ///
+    /// [`EntityFabricator`]: crate::EntityFabricator
+    fn assemble_with<'a, 'w>(
+        &self,
+        builder: EntityBuilder<'a, 'w>,
+        node: &KdlNode,
+        assembly: &mut Assembly<'_, Ctx>,
+    ) -> eyre::Result<EntityBuilder<'a, 'w>> {
+        match assembly.ctx_mut() {
+            Some(ctx) => self.assemble_mut(builder, node, ctx),
+            None => self.assemble(builder, node, assembly.ctx()),
+        }
+    }
+
+    /// Check if a node could be assembled, without actually adding anything to an entity.
+    ///
+    /// This is what [`EntityFabricator::validate_one`] uses. By default it
+    /// doesn't check anything, so factories that can tell a bad node apart
+    /// without a builder should implement it.
+    ///
+    /// [`EntityFabricator::validate_one`]: crate::EntityFabricator::validate_one
+    fn check(&self, node: &KdlNode, ctx: &Ctx) -> eyre::Result<()> {
+        let _ = (node, ctx);
+        Ok(())
+    }
+}
+
+/// What [`ComponentFactory::assemble_with`] gets to work with besides the builder and the node.
+pub struct Assembly<'c, Ctx> {
+    ctx: CtxRef<'c, Ctx>,
+    resources: &'c Resources,
+}
+
+enum CtxRef<'c, Ctx> {
+    Shared(&'c Ctx),
+    Mut(&'c mut Ctx),
+}
+
+impl<'c, Ctx> Assembly<'c, Ctx> {
+    /// Assemble with a shared context, like [`EntityFabricator::instantiate`] does.
+    ///
+    /// [`EntityFabricator::instantiate`]: crate::EntityFabricator::instantiate
+    pub fn new(ctx: &'c Ctx, resources: &'c Resources) -> Self {
+        Self {
+            ctx: CtxRef::Shared(ctx),
+            resources,
+        }
+    }
+
+    /// Assemble with a mutable context, like [`EntityFabricator::instantiate_mut`] does.
+    ///
+    /// [`EntityFabricator::instantiate_mut`]: crate::EntityFabricator::instantiate_mut
+    pub fn new_mut(ctx: &'c mut Ctx, resources: &'c Resources) -> Self {
+        Self {
+            ctx: CtxRef::Mut(ctx),
+            resources,
+        }
+    }
+
+    pub fn ctx(&self) -> &Ctx {
+        match &self.ctx {
+            CtxRef::Shared(ctx) => ctx,
+            CtxRef::Mut(ctx) => ctx,
+        }
+    }
+
+    /// Get the context mutably, if it was passed in mutably.
+    pub fn ctx_mut(&mut self) -> Option<&mut Ctx> {
+        match &mut self.ctx {
+            CtxRef::Shared(_) => None,
+            CtxRef::Mut(ctx) => Some(ctx),
+        }
+    }
+
+    pub fn resources(&self) -> &'c Resources {
+        self.resources
+    }
 }
 
 /// Convenience wrapper for the common case where you want to just deserialize something from
 /// a node with serde.
 ///
 /// Doesn't use the `Ctx` generic (just has it in PhantomData).
-// the funky generic in the Phantom Data is due to irritating send/sync reasons
-pub struct SerdeComponentFactory<T, Ctx>(PhantomData<fn(&Ctx) -> T>);
+pub struct SerdeComponentFactory<T, Ctx> {
+    defaults: Option<KdlNode>,
+    // the funky generic in the Phantom Data is due to irritating send/sync reasons
+    phantom: PhantomData<fn(&Ctx) -> T>,
+}
 
 impl<T, Ctx> SerdeComponentFactory<T, Ctx> {
     pub fn new() -> Self {
-        Self(PhantomData)
+        Self {
+            defaults: None,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Make a factory that fills in anything a blueprint's node leaves out
+    /// from the `defaults` node before deserializing it.
+    ///
+    /// - Properties and children are copied over if the node doesn't have
+    ///   that key as either a property or a child.
+    /// - If the node has no arguments at all, the defaults' arguments are used.
+    ///
+    /// The name of the defaults node doesn't matter.
+    pub fn with_defaults(defaults: KdlNode) -> Self {
+        Self {
+            defaults: Some(defaults),
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<T, Ctx> Default for SerdeComponentFactory<T, Ctx> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: DeserializeOwned, Ctx> SerdeComponentFactory<T, Ctx> {
+    fn deserialize(&self, node: &KdlNode) -> eyre::Result<T> {
+        let comp = match &self.defaults {
+            Some(defaults) => {
+                knurdy::deserialize_node(&merge_defaults(node, defaults))?
+            }
+            None => knurdy::deserialize_node(node)?,
+        };
+        Ok(comp)
     }
 }
 
@@ -47,8 +194,236 @@ where
         node: &KdlNode,
         _ctx: &Ctx,
     ) -> eyre::Result<EntityBuilder<'a, 'w>> {
-        let comp: T = knurdy::deserialize_node(node)?;
-        builder.insert(comp);
+        builder.insert(self.deserialize(node)?);
         Ok(builder)
     }
+
+    fn check(&self, node: &KdlNode, _ctx: &Ctx) -> eyre::Result<()> {
+        self.deserialize(node).map(|_| ())
+    }
+}
+
+/// Fill in anything `node` leaves out from `defaults`.
+fn merge_defaults(node: &KdlNode, defaults: &KdlNode) -> KdlNode {
+    // A field might be given as either a property or a child, and knurdy
+    // takes `start-hp` and `start_hp` as the same field
+    let fields = node
+        .entries()
+        .iter()
+        .filter_map(|entry| entry.name())
+        .chain(
+            node.children()
+                .into_iter()
+                .flat_map(|kids| kids.nodes().iter().map(|kid| kid.name())),
+        )
+        .map(|key| key.value().to_snake_case())
+        .collect::<AHashSet<_>>();
+    let has_field = |key: &str| fields.contains(&key.to_snake_case());
+    let has_args = node.entries().iter().any(|entry| entry.name().is_none());
+
+    let mut out = node.clone();
+    for entry in defaults.entries() {
+        let missing = match entry.name() {
+            Some(key) => !has_field(key.value()),
+            None => !has_args,
+        };
+        if missing {
+            out.push(entry.clone());
+        }
+    }
+
+    if let Some(default_kids) = defaults.children() {
+        for kid in default_kids.nodes() {
+            if !has_field(kid.name().value()) {
+                out.ensure_children().nodes_mut().push(kid.clone());
+            }
+        }
+    }
+
+    out
+}
+
+/// Factory for marker components that are toggled on or off by a node like
+/// `invisible`, `invisible true`, or `invisible false`.
+///
+/// With no argument or `true` it inserts `T::default()`; with `false` it just
+/// skips inserting it.
+///
+/// Turning off a marker that something earlier in the blueprint already
+/// added, like a splice, isn't supported, since palkia can't take a
+/// component back off a builder. That's an error, rather than leaving the
+/// marker on without saying so. To turn off a marker from another
+/// blueprint, merge over its node instead, so the `false` node replaces it.
+pub struct MarkerComponentFactory<T, Ctx>(PhantomData<fn(&Ctx) -> T>);
+
+impl<T, Ctx> MarkerComponentFactory<T, Ctx> {
+    pub fn new() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<T, Ctx> Default for MarkerComponentFactory<T, Ctx> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, Ctx> ComponentFactory<Ctx> for MarkerComponentFactory<T, Ctx>
+where
+    Self: 'static,
+    T: Default + Component,
+{
+    fn assemble<'a, 'w>(
+        &self,
+        mut builder: EntityBuilder<'a, 'w>,
+        node: &KdlNode,
+        _ctx: &Ctx,
+    ) -> eyre::Result<EntityBuilder<'a, 'w>> {
+        if marker_enabled(node)? {
+            builder.insert(T::default());
+        } else if builder.get_component::<T>().is_some() {
+            bail!("`false` can't turn off a marker that was already added");
+        }
+        Ok(builder)
+    }
+
+    fn check(&self, node: &KdlNode, _ctx: &Ctx) -> eyre::Result<()> {
+        marker_enabled(node).map(|_| ())
+    }
+}
+
+fn marker_enabled(node: &KdlNode) -> eyre::Result<bool> {
+    if node.children().is_some() {
+        bail!("marker components can't have children");
+    }
+    let enabled = match node.entries() {
+        [] => true,
+        [entry] if entry.name().is_none() => match entry.value().as_bool() {
+            Some(it) => it,
+            None => bail!("marker argument must be `true` or `false`"),
+        },
+        _ => bail!("markers can only have one argument and no properties"),
+    };
+    Ok(enabled)
+}
+
+/// Factory that changes a component already on the entity instead of adding
+/// a new one, for nodes that add to a component from an earlier node.
+///
+/// The node is deserialized with serde into a `P`, which is then applied to
+/// the builder's `C` with the `apply` function. It's an error if the builder
+/// doesn't have a `C` yet, so the patching node has to come after whatever
+/// adds the component.
+pub struct PatchComponentFactory<C, P, F, Ctx> {
+    apply: F,
+    phantom: PhantomData<fn(&Ctx, P) -> C>,
+}
+
+impl<C, P, F, Ctx> PatchComponentFactory<C, P, F, Ctx>
+where
+    F: Fn(&mut C, P),
+{
+    pub fn new(apply: F) -> Self {
+        Self {
+            apply,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<C, P, F, Ctx> ComponentFactory<Ctx> for PatchComponentFactory<C, P, F, Ctx>
+where
+    Self: 'static,
+    C: Component,
+    P: DeserializeOwned,
+    F: Fn(&mut C, P) + Send + Sync,
+{
+    fn assemble<'a, 'w>(
+        &self,
+        mut builder: EntityBuilder<'a, 'w>,
+        node: &KdlNode,
+        _ctx: &Ctx,
+    ) -> eyre::Result<EntityBuilder<'a, 'w>> {
+        let patch: P = knurdy::deserialize_node(node)?;
+        match builder.get_component_mut::<C>() {
+            Some(comp) => (self.apply)(comp, patch),
+            None => bail!(
+                "there's no {} on the entity to patch",
+                std::any::type_name::<C>()
+            ),
+        }
+        Ok(builder)
+    }
+
+    fn check(&self, node: &KdlNode, _ctx: &Ctx) -> eyre::Result<()> {
+        knurdy::deserialize_node::<P>(node)?;
+        Ok(())
+    }
+}
+
+/// Wraps another factory so it only runs when a predicate on the context holds.
+///
+/// When the predicate fails the node is skipped and nothing is added. The
+/// check happens per node at instantiation time, so it doesn't matter if the
+/// node was written in the blueprint itself or came in through a splice.
+pub struct ConditionalComponentFactory<CF, P> {
+    factory: CF,
+    predicate: P,
+}
+
+impl<CF, P> ConditionalComponentFactory<CF, P> {
+    pub fn new(factory: CF, predicate: P) -> Self {
+        Self { factory, predicate }
+    }
+}
+
+impl<CF, P, Ctx> ComponentFactory<Ctx> for ConditionalComponentFactory<CF, P>
+where
+    Ctx: 'static,
+    CF: ComponentFactory<Ctx>,
+    P: Fn(&Ctx) -> bool + Send + Sync + 'static,
+{
+    fn assemble<'a, 'w>(
+        &self,
+        builder: EntityBuilder<'a, 'w>,
+        node: &KdlNode,
+        ctx: &Ctx,
+    ) -> eyre::Result<EntityBuilder<'a, 'w>> {
+        if (self.predicate)(ctx) {
+            self.factory.assemble(builder, node, ctx)
+        } else {
+            Ok(builder)
+        }
+    }
+
+    fn assemble_mut<'a, 'w>(
+        &self,
+        builder: EntityBuilder<'a, 'w>,
+        node: &KdlNode,
+        ctx: &mut Ctx,
+    ) -> eyre::Result<EntityBuilder<'a, 'w>> {
+        if (self.predicate)(ctx) {
+            self.factory.assemble_mut(builder, node, ctx)
+        } else {
+            Ok(builder)
+        }
+    }
+
+    fn assemble_with<'a, 'w>(
+        &self,
+        builder: EntityBuilder<'a, 'w>,
+        node: &KdlNode,
+        assembly: &mut Assembly<'_, Ctx>,
+    ) -> eyre::Result<EntityBuilder<'a, 'w>> {
+        if (self.predicate)(assembly.ctx()) {
+            self.factory.assemble_with(builder, node, assembly)
+        } else {
+            Ok(builder)
+        }
+    }
+
+    fn check(&self, node: &KdlNode, ctx: &Ctx) -> eyre::Result<()> {
+        // Check even if the predicate doesn't hold right now; it might later
+        self.factory.check(node, ctx)
+    }
 }
@@ -1,13 +1,30 @@
 #![doc = include_str!("../README.md")]
 
 pub mod blueprint;
+pub mod diff;
 pub mod factory;
+pub mod resource;
 
-use std::collections::BTreeMap;
+use std::{
+    any::Any,
+    collections::{BTreeMap, BTreeSet},
+    io::Read,
+    sync::Arc,
+};
 
-use blueprint::{BlueprintLibrary, BlueprintLookupError, BlueprintParseError};
-use factory::{ComponentFactory, SerdeComponentFactory};
+use blueprint::{
+    Blueprint, BlueprintLibrary, BlueprintLookupError, BlueprintParseError,
+    BlueprintParseErrors, LoadWarning, RawBlueprint, RawBlueprintDeserError,
+};
+use factory::{
+    Assembly, ComponentFactory, ConditionalComponentFactory,
+    MarkerComponentFactory, PatchComponentFactory, SerdeComponentFactory,
+};
+use resource::Resources;
 
+use heck::ToKebabCase;
+use kdl::{KdlDocument, KdlNode};
+use miette::NamedSource;
 use palkia::prelude::*;
 use serde::de::DeserializeOwned;
 use smol_str::SmolStr;
@@ -21,6 +38,7 @@ pub struct EntityFabricator<Ctx> {
     blueprints: BlueprintLibrary,
     /// Map component names to factories for it.
     assemblers: BTreeMap<SmolStr, Box<dyn ComponentFactory<Ctx>>>,
+    resources: Resources,
 }
 
 impl<Ctx> EntityFabricator<Ctx>
@@ -31,23 +49,48 @@ where
         Self {
             blueprints: BlueprintLibrary::new(),
             assemblers: BTreeMap::new(),
+            resources: Resources::new(),
         }
     }
 
     /// Register a component assembler.
+    ///
+    /// If the component just needs deserializing with serde, use
+    /// [`register_serde`][EntityFabricator::register_serde] instead; it only
+    /// needs the type and the name.
     pub fn register<CA: ComponentFactory<Ctx>>(
         &mut self,
         name: &str,
         factory: CA,
     ) {
-        if let Some(_) = self
+        if self
             .assemblers
             .insert(SmolStr::from(name), Box::new(factory))
+            .is_some()
         {
             panic!("already registered something under the name {:?}", name);
         }
     }
 
+    /// Register a component assembler that only runs when `predicate` returns
+    /// true for the context passed to `instantiate`.
+    ///
+    /// See [`ConditionalComponentFactory`].
+    pub fn register_when<CA, P>(
+        &mut self,
+        name: &str,
+        factory: CA,
+        predicate: P,
+    ) where
+        CA: ComponentFactory<Ctx>,
+        P: Fn(&Ctx) -> bool + Send + Sync + 'static,
+    {
+        self.register(
+            name,
+            ConditionalComponentFactory::new(factory, predicate),
+        )
+    }
+
     /// Convenience function to register an assembler that just loads the thing with serde.
     pub fn register_serde<C: DeserializeOwned + Component>(
         &mut self,
@@ -56,6 +99,57 @@ where
         self.register(name, SerdeComponentFactory::<C, Ctx>::new())
     }
 
+    /// Like [`register_serde`][EntityFabricator::register_serde], but the
+    /// name is worked out from the type with [`derived_component_name`], so
+    /// `HasHP` is registered as `has-hp`.
+    ///
+    /// If the derived name isn't what you want, use `register_serde` with the
+    /// name spelled out instead.
+    pub fn register_serde_auto<C: DeserializeOwned + Component>(&mut self) {
+        self.register_serde::<C>(&derived_component_name::<C>())
+    }
+
+    /// Convenience function to register an assembler that loads the thing
+    /// with serde, filling in anything the blueprint leaves out from
+    /// `defaults`.
+    ///
+    /// See [`SerdeComponentFactory::with_defaults`] for how they're merged.
+    pub fn register_serde_with_defaults<C: DeserializeOwned + Component>(
+        &mut self,
+        name: &str,
+        defaults: KdlNode,
+    ) {
+        self.register(
+            name,
+            SerdeComponentFactory::<C, Ctx>::with_defaults(defaults),
+        )
+    }
+
+    /// Register an assembler that changes a `C` already on the entity
+    /// instead of adding one, by deserializing the node into a `P` and
+    /// passing both to `apply`.
+    ///
+    /// See [`PatchComponentFactory`] for details.
+    pub fn register_patch<C, P>(
+        &mut self,
+        name: &str,
+        apply: impl Fn(&mut C, P) + Send + Sync + 'static,
+    ) where
+        C: Component,
+        P: DeserializeOwned + 'static,
+    {
+        self.register(name, PatchComponentFactory::new(apply))
+    }
+
+    /// Convenience function to register a [`MarkerComponentFactory`], for
+    /// components that are just switched on or off.
+    ///
+    /// `false` only skips adding the marker; it can't turn off one a splice
+    /// already added.
+    pub fn register_marker<C: Default + Component>(&mut self, name: &str) {
+        self.register(name, MarkerComponentFactory::<C, Ctx>::new())
+    }
+
     /// Load the KDL string into the fabricator as a list of blueprints.
     ///
     /// The `filepath` argument is just for error reporting purposes; this doesn't load anything from disc.
@@ -67,51 +161,682 @@ where
         self.blueprints.load_str(src, filepath)
     }
 
+    /// Load an already-parsed KDL document into the fabricator as a list of
+    /// blueprints, without turning it back into text first.
+    ///
+    /// The `src` argument is just for error reporting purposes.
+    pub fn load_document(
+        &mut self,
+        doc: &KdlDocument,
+        src: impl Into<Arc<NamedSource>>,
+    ) -> Result<(), RawBlueprintDeserError> {
+        self.blueprints.load_document(doc, src)
+    }
+
+    /// Load several KDL strings into the fabricator, given as
+    /// `(src, filepath)` pairs.
+    ///
+    /// Keeps going past bad files and reports all of their errors at once.
+    pub fn load_strs<'a>(
+        &mut self,
+        srcs: impl IntoIterator<Item = (&'a str, &'a str)>,
+    ) -> Result<(), BlueprintParseErrors> {
+        self.blueprints.load_strs(srcs)
+    }
+
+    /// Load UTF-8 bytes into the fabricator as a list of blueprints.
+    ///
+    /// The `filepath` argument is just for error reporting purposes.
+    pub fn load_bytes(
+        &mut self,
+        src: &[u8],
+        filepath: &str,
+    ) -> Result<(), BlueprintParseError> {
+        self.blueprints.load_bytes(src, filepath)
+    }
+
+    /// Read everything out of the reader and load it into the fabricator as a
+    /// list of blueprints.
+    ///
+    /// The `filepath` argument is just for error reporting purposes; the
+    /// reader is what actually gets read.
+    pub fn load_reader(
+        &mut self,
+        reader: impl Read,
+        filepath: &str,
+    ) -> Result<(), BlueprintParseError> {
+        self.blueprints.load_reader(reader, filepath)
+    }
+
+    /// Insert a single blueprint, say one picked out of
+    /// [`BlueprintLibrary::iter_raw_from_str`].
+    ///
+    /// It's merged with or replaces any blueprint of the same name just like
+    /// with the other loading methods.
+    pub fn insert_raw(&mut self, raw: RawBlueprint) {
+        self.blueprints.insert_raw(raw);
+    }
+
+    /// Throw away all the loaded blueprints, but keep the registered
+    /// assemblers.
+    ///
+    /// For reloading all the content from scratch.
+    pub fn clear_blueprints(&mut self) {
+        self.blueprints.clear();
+    }
+
+    /// Put the fabricator back the way [`new`][EntityFabricator::new] made
+    /// it.
+    ///
+    /// This throws away the loaded blueprints and the registered assemblers,
+    /// and everything else set up on the fabricator too: migrations,
+    /// deprecated aliases, repeatable components, flags, resources, the
+    /// duplicate warning setting, and any warnings not taken yet.
+    pub fn clear_all(&mut self) {
+        self.blueprints = BlueprintLibrary::new();
+        self.assemblers.clear();
+        self.resources = Resources::new();
+    }
+
+    /// Mark a component as repeatable: a blueprint can have several of it,
+    /// each meaning something on its own, like several `effect` nodes.
+    ///
+    /// Normally a blueprint merged over another replaces the old blueprint's
+    /// component with the same name. For repeatable components, it adds its
+    /// nodes after the old ones instead. Splices never replace anything, so
+    /// they work the same either way; to get rid of a repeatable component
+    /// from another blueprint, use `merge="clobber"`.
+    ///
+    /// Every node is passed to the assembler separately. Palkia only allows
+    /// one component of each type per entity, so the assembler has to combine
+    /// them itself, say by pushing onto a list in a component it gets from
+    /// [`EntityBuilder::get_component_mut`].
+    ///
+    /// This affects how blueprints are merged when they're loaded, so do it
+    /// before loading any.
+    pub fn mark_repeatable(&mut self, component: &str) {
+        self.blueprints.set_repeatable(component);
+    }
+
+    /// Register a function to upgrade components named `component` written
+    /// for schema version `from` to version `from + 1`.
+    ///
+    /// Blueprints say which version they were written for with a top-level
+    /// `schema=` key, and are version [`DEFAULT_SCHEMA`] if they don't. When
+    /// a blueprint is loaded, each of its components is run through every
+    /// migration for it from the blueprint's version on, oldest first.
+    /// Versions a component has no migration for are skipped. The node can be
+    /// changed in any way, including renaming it.
+    ///
+    /// Migrations only apply to blueprints loaded after they're registered.
+    ///
+    /// [`DEFAULT_SCHEMA`]: blueprint::DEFAULT_SCHEMA
+    pub fn register_migration(
+        &mut self,
+        component: &str,
+        from: u32,
+        migration: impl Fn(&mut KdlNode) + Send + Sync + 'static,
+    ) {
+        self.blueprints
+            .register_migration(component, from, migration);
+    }
+
+    /// Insert a resource that every assembler can get at while instantiating,
+    /// returning the old one of the same type if there was one.
+    ///
+    /// See [`Resources`] for more.
+    pub fn insert_resource<R: Any + Send + Sync>(
+        &mut self,
+        resource: R,
+    ) -> Option<R> {
+        self.resources.insert(resource)
+    }
+
+    /// Get the resource of the given type.
+    pub fn get_resource<R: Any + Send + Sync>(&self) -> Option<&R> {
+        self.resources.get()
+    }
+
+    /// Get all the resources.
+    pub fn resources(&self) -> &Resources {
+        &self.resources
+    }
+
+    /// Get all the resources mutably.
+    ///
+    /// Assemblers only ever see resources through a shared reference during
+    /// instantiation, so this can't be called while instantiating.
+    pub fn resources_mut(&mut self) -> &mut Resources {
+        &mut self.resources
+    }
+
+    /// Keep blueprints using the old name of a renamed component working:
+    /// components named `old` are assembled with `new`'s assembler.
+    ///
+    /// Each use of `old` in a blueprint loaded afterwards is reported once, in
+    /// [`take_warnings`][EntityFabricator::take_warnings].
+    pub fn register_deprecated_alias(&mut self, old: &str, new: &str) {
+        self.blueprints.set_deprecated_alias(old, new);
+    }
+
+    /// Turn on or off warning about components written twice in the same
+    /// blueprint, which is usually a copy-paste mistake. It's off by default.
+    ///
+    /// Components marked [repeatable][EntityFabricator::mark_repeatable]
+    /// are fine to write several times and aren't warned about.
+    pub fn set_warn_duplicates(&mut self, warn: bool) {
+        self.blueprints.set_warn_duplicates(warn);
+    }
+
+    /// Take all the warnings collected while loading blueprints so far.
+    ///
+    /// They're [`Diagnostic`][miette::Diagnostic]s, so they can be printed
+    /// nicely with miette.
+    pub fn take_warnings(&mut self) -> Vec<LoadWarning> {
+        self.blueprints.take_warnings()
+    }
+
+    /// Turn a flag on or off.
+    ///
+    /// Blueprints with `when="flag"` only exist while the flag is on, and
+    /// ones with `when="!flag"` only while it's off. Flags are checked when
+    /// blueprints are looked up, so they can be changed at any time.
+    pub fn set_flag(&mut self, flag: &str, enabled: bool) {
+        self.blueprints.set_flag(flag, enabled);
+    }
+
+    /// Check if a flag is turned on.
+    pub fn flag_enabled(&self, flag: &str) -> bool {
+        self.blueprints.flag_enabled(flag)
+    }
+
+    /// Count up how much content is loaded into the fabricator.
+    pub fn stats(&self) -> FabricatorStats {
+        self.blueprints.stats()
+    }
+
+    /// Get the names of all registered assemblers that no loaded blueprint uses.
+    ///
+    /// Handy for finding component types that no content needs anymore.
+    pub fn unused_assemblers(&self) -> BTreeSet<SmolStr> {
+        let used = self
+            .blueprints
+            .component_names()
+            .map(|name| match self.blueprints.deprecated_alias(name) {
+                Some(new) if !self.assemblers.contains_key(name) => {
+                    new.as_str()
+                }
+                _ => name,
+            })
+            .collect::<BTreeSet<_>>();
+        self.assemblers
+            .keys()
+            .filter(|name| !used.contains(name.as_str()))
+            .cloned()
+            .collect()
+    }
+
+    /// Get the names of every component the given blueprints end up with,
+    /// splices included.
+    ///
+    /// Handy for making sure every component the blueprints need is
+    /// registered, both here and with the `World`.
+    pub fn required_components<'n>(
+        &self,
+        blueprints: impl IntoIterator<Item = &'n str>,
+    ) -> Result<BTreeSet<SmolStr>, BlueprintLookupError> {
+        let mut out = BTreeSet::new();
+        for name in blueprints {
+            let print = self.blueprints.lookup(name)?;
+            out.extend(
+                print
+                    .components
+                    .iter()
+                    .map(|node| SmolStr::from(node.name().value())),
+            );
+        }
+        Ok(out)
+    }
+
+    /// Get the names of every component the given blueprints need that has no
+    /// assembler registered, even through a deprecated alias.
+    pub fn missing_assemblers<'n>(
+        &self,
+        blueprints: impl IntoIterator<Item = &'n str>,
+    ) -> Result<BTreeSet<SmolStr>, BlueprintLookupError> {
+        let mut required = self.required_components(blueprints)?;
+        required.retain(|name| self.assembler_for(name).is_none());
+        Ok(required)
+    }
+
+    /// Get the names of all the blueprints spliced into the given one, in the
+    /// order they get folded in.
+    ///
+    /// A blueprint spliced more than once in the same body shows up each time,
+    /// but one reached again through a different splice (like the shared base
+    /// of a diamond) only contributes its components, and shows up, once.
+    ///
+    /// Useful for figuring out where a component on a blueprint came from.
+    pub fn inheritance_chain(
+        &self,
+        name: &str,
+    ) -> Result<Vec<SmolStr>, BlueprintLookupError> {
+        self.blueprints.inheritance_chain(name)
+    }
+
+    /// Get the names of all the blueprints that splice in the given one,
+    /// directly or through other splices.
+    ///
+    /// Handy for working out what changing a base blueprint will affect.
+    /// Blueprints turned off with `when=` count too, since they'd be
+    /// affected as soon as their flag changes.
+    pub fn descendants_of(&self, name: &str) -> Vec<SmolStr> {
+        self.blueprints.descendants_of(name)
+    }
+
+    /// Look up a blueprint and fold in everything it splices, so it can be
+    /// instantiated many times without doing that work again.
+    ///
+    /// The returned [`Blueprint`] is a snapshot; loading more blueprints
+    /// afterwards won't change it.
+    pub fn resolve(
+        &self,
+        name: &str,
+    ) -> Result<Blueprint, BlueprintLookupError> {
+        self.blueprints.lookup(name)
+    }
+
+    /// Check that a blueprint can be looked up and that every one of its
+    /// components has an assembler that accepts it, without making an entity.
+    ///
+    /// Unlike instantiating, this doesn't stop at the first problem; every bad
+    /// component is reported. The components are checked with
+    /// [`ComponentFactory::check`], so factories that don't implement it only
+    /// get checked for existing.
+    pub fn validate_one(
+        &self,
+        name: &str,
+        ctx: &Ctx,
+    ) -> Result<(), Vec<InstantiationError>> {
+        let print = self
+            .blueprints
+            .lookup(name)
+            .map_err(|err| vec![err.into()])?;
+        let errors = print
+            .components
+            .iter()
+            .filter_map(|node| {
+                let name = node.name().value();
+                match self.assembler_for(name) {
+                    Some(factory) => {
+                        factory.check(node, ctx).err().map(|err| {
+                            InstantiationError::AssemblerError(name.into(), err)
+                        })
+                    }
+                    None => Some(self.no_assembler(name)),
+                }
+            })
+            .collect::<Vec<_>>();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
     /// Instantiate an entity from a blueprint, adding all the components in that blueprint
     /// to the builder.
     ///
     /// Note that the builder doesn't have to be empty! For example, you might want to add a component for
     /// its position before filling it with other information.
+    /// If the blueprint has a component of a type that's already in the builder, the blueprint's
+    /// replaces it.
+    ///
+    /// Palkia doesn't let you add components to an entity once it's built, so there's no way to apply
+    /// a blueprint to an existing entity. To "promote" an entity, spawn a new one with its old
+    /// components in the builder, instantiate the blueprint into that, and despawn the old one.
     pub fn instantiate_to_builder<'a, 'w>(
         &self,
         name: &str,
-        mut builder: EntityBuilder<'a, 'w>,
+        builder: EntityBuilder<'a, 'w>,
+        ctx: &Ctx,
+    ) -> Result<EntityBuilder<'a, 'w>, InstantiationError> {
+        let mut assembly = Assembly::new(ctx, &self.resources);
+        self.instantiate_with(name, builder, |factory, builder, node| {
+            factory.assemble_with(builder, node, &mut assembly)
+        })
+    }
+
+    /// Convenience method to just return the entity off the builder instead of returning it.
+    pub fn instantiate<'a, 'w>(
+        &self,
+        name: &str,
+        builder: EntityBuilder<'a, 'w>,
+        ctx: &Ctx,
+    ) -> Result<Entity, InstantiationError> {
+        Ok(self.instantiate_to_builder(name, builder, ctx)?.build())
+    }
+
+    /// Spawn a new entity in the world and fill it in from a blueprint,
+    /// returning the builder so more components can be added before it's
+    /// built.
+    ///
+    /// Shorthand for `instantiate_to_builder(name, world.spawn(), ctx)`.
+    pub fn fabricate<'w>(
+        &self,
+        name: &str,
+        world: &'w mut World,
+        ctx: &Ctx,
+    ) -> Result<EntityBuilder<'w, 'w>, InstantiationError> {
+        self.instantiate_to_builder(name, world.spawn(), ctx)
+    }
+
+    /// Like [`instantiate_to_builder`][EntityFabricator::instantiate_to_builder],
+    /// but the context is passed mutably, so factories can change it through
+    /// [`ComponentFactory::assemble_mut`].
+    pub fn instantiate_to_builder_mut<'a, 'w>(
+        &self,
+        name: &str,
+        builder: EntityBuilder<'a, 'w>,
+        ctx: &mut Ctx,
+    ) -> Result<EntityBuilder<'a, 'w>, InstantiationError> {
+        let mut assembly = Assembly::new_mut(ctx, &self.resources);
+        self.instantiate_with(name, builder, |factory, builder, node| {
+            factory.assemble_with(builder, node, &mut assembly)
+        })
+    }
+
+    /// Convenience method to just return the entity off the builder instead of returning it.
+    pub fn instantiate_mut<'a, 'w>(
+        &self,
+        name: &str,
+        builder: EntityBuilder<'a, 'w>,
+        ctx: &mut Ctx,
+    ) -> Result<Entity, InstantiationError> {
+        Ok(self.instantiate_to_builder_mut(name, builder, ctx)?.build())
+    }
+
+    /// Like [`instantiate_to_builder`][EntityFabricator::instantiate_to_builder],
+    /// but with a blueprint already gotten from [`resolve`][EntityFabricator::resolve].
+    pub fn instantiate_resolved_to_builder<'a, 'w>(
+        &self,
+        print: &Blueprint,
+        builder: EntityBuilder<'a, 'w>,
+        ctx: &Ctx,
+    ) -> Result<EntityBuilder<'a, 'w>, InstantiationError> {
+        let mut assembly = Assembly::new(ctx, &self.resources);
+        self.assemble_all(print, builder, |factory, builder, node| {
+            factory.assemble_with(builder, node, &mut assembly)
+        })
+    }
+
+    /// Convenience method to just return the entity off the builder instead of returning it.
+    pub fn instantiate_resolved<'a, 'w>(
+        &self,
+        print: &Blueprint,
+        builder: EntityBuilder<'a, 'w>,
         ctx: &Ctx,
+    ) -> Result<Entity, InstantiationError> {
+        Ok(self
+            .instantiate_resolved_to_builder(print, builder, ctx)?
+            .build())
+    }
+
+    /// Look up the blueprint and run `assemble` on each of its components.
+    fn instantiate_with<'a, 'w>(
+        &self,
+        name: &str,
+        builder: EntityBuilder<'a, 'w>,
+        assemble: impl FnMut(
+            &dyn ComponentFactory<Ctx>,
+            EntityBuilder<'a, 'w>,
+            &KdlNode,
+        ) -> eyre::Result<EntityBuilder<'a, 'w>>,
+    ) -> Result<EntityBuilder<'a, 'w>, InstantiationError> {
+        #[cfg(feature = "tracing")]
+        let _span =
+            tracing::debug_span!("instantiate", blueprint = name).entered();
+
+        let print = self.blueprints.lookup(name);
+        // The error goes back to the caller, so it's only worth a debug here
+        #[cfg(feature = "tracing")]
+        if let Err(err) = &print {
+            tracing::debug!(%err, "failed to resolve blueprint");
+        }
+        self.assemble_all(&print?, builder, assemble)
+    }
+
+    /// Find the assembler for a component, going through deprecated aliases if
+    /// there isn't one registered under that name.
+    fn assembler_for(&self, name: &str) -> Option<&dyn ComponentFactory<Ctx>> {
+        if let Some(factory) = self.assemblers.get(name) {
+            return Some(factory.as_ref());
+        }
+        let new = self.blueprints.deprecated_alias(name)?;
+        self.assemblers.get(new).map(|factory| factory.as_ref())
+    }
+
+    /// Make the error for a component with no assembler, suggesting the
+    /// closest registered name in case it's a typo.
+    fn no_assembler(&self, name: &str) -> InstantiationError {
+        // Allow about one typo per three letters, but always at least one
+        let max_distance = (name.chars().count() / 3).max(1);
+        let suggestion = self
+            .assemblers
+            .keys()
+            .map(|candidate| (edit_distance(name, candidate), candidate))
+            .filter(|(distance, _)| *distance <= max_distance)
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(_, candidate)| candidate.clone());
+        InstantiationError::NoAssembler(name.into(), suggestion)
+    }
+
+    /// Run `assemble` on each of the blueprint's components with the factory
+    /// registered for it.
+    fn assemble_all<'a, 'w>(
+        &self,
+        print: &Blueprint,
+        mut builder: EntityBuilder<'a, 'w>,
+        mut assemble: impl FnMut(
+            &dyn ComponentFactory<Ctx>,
+            EntityBuilder<'a, 'w>,
+            &KdlNode,
+        ) -> eyre::Result<EntityBuilder<'a, 'w>>,
     ) -> Result<EntityBuilder<'a, 'w>, InstantiationError> {
-        let print = self.blueprints.lookup(name)?;
+        #[cfg(feature = "tracing")]
+        let _span =
+            tracing::debug_span!("assemble", blueprint = %print.name).entered();
+        #[cfg(feature = "tracing")]
+        let mut seen = BTreeSet::new();
 
-        for node in print.components {
+        for node in print.components.iter() {
             let name = node.name().value();
-            let factory = self
-                .assemblers
-                .get(name)
-                .ok_or_else(|| InstantiationError::NoAssembler(name.into()))?;
-            builder = factory.assemble(builder, &node, ctx).map_err(|err| {
-                InstantiationError::AssemblerError(name.into(), err)
-            })?
+            #[cfg(feature = "tracing")]
+            if !seen.insert(name) && !self.blueprints.is_repeatable(name) {
+                tracing::warn!(
+                    component = name,
+                    "component overrides an earlier one with the same name"
+                );
+            }
+
+            let assembled = match self.assembler_for(name) {
+                Some(factory) => {
+                    assemble(factory, builder, node).map_err(|err| {
+                        InstantiationError::AssemblerError(name.into(), err)
+                    })
+                }
+                None => Err(self.no_assembler(name)),
+            };
+            #[cfg(feature = "tracing")]
+            match &assembled {
+                Ok(_) => {
+                    tracing::debug!(component = name, "assembled component")
+                }
+                Err(err) => tracing::debug!(
+                    component = name,
+                    %err,
+                    "failed to assemble component"
+                ),
+            }
+            builder = assembled?;
         }
 
         Ok(builder)
     }
+}
 
-    /// Convenience method to just return the entity off the builder instead of returning it.
-    pub fn instantiate<'a, 'w>(
+impl<Ctx> Default for EntityFabricator<Ctx>
+where
+    Ctx: 'static,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The name [`EntityFabricator::register_serde_auto`] registers a type under:
+/// the type's name without its module path or generics, in kebab-case.
+///
+/// For example, `my_game::HasHP` becomes `has-hp`, and `Wrapper<u32>` becomes
+/// `wrapper`.
+pub fn derived_component_name<C: ?Sized>() -> String {
+    let full = std::any::type_name::<C>();
+    let without_generics = full.split('<').next().unwrap_or(full);
+    let ty = without_generics
+        .rsplit("::")
+        .next()
+        .unwrap_or(without_generics);
+    ty.to_kebab_case()
+}
+
+/// Object-safe face of an [`EntityFabricator`], for code that can't name its
+/// context type.
+///
+/// This is implemented for every fabricator whose context implements
+/// [`Default`] (including `()`); each entity is instantiated with a fresh
+/// `Ctx::default()`. So a `Box<dyn Fabricator>` can be handed to plugins
+/// that only need to spawn things by name.
+pub trait Fabricator {
+    /// Like [`EntityFabricator::instantiate`], with a default context.
+    fn instantiate_erased<'a, 'w>(
+        &self,
+        name: &str,
+        builder: EntityBuilder<'a, 'w>,
+    ) -> Result<Entity, InstantiationError>;
+}
+
+impl<Ctx> Fabricator for EntityFabricator<Ctx>
+where
+    Ctx: Default + 'static,
+{
+    fn instantiate_erased<'a, 'w>(
         &self,
         name: &str,
         builder: EntityBuilder<'a, 'w>,
-        ctx: &Ctx,
     ) -> Result<Entity, InstantiationError> {
-        Ok(self.instantiate_to_builder(name, builder, ctx)?.build())
+        self.instantiate(name, builder, &Ctx::default())
     }
 }
 
+/// Register a whole list of components with
+/// [`register_serde`][EntityFabricator::register_serde] at once.
+///
+/// ```
+/// # use dialga::EntityFabricator;
+/// # use palkia::prelude::*;
+/// # use serde::Deserialize;
+/// #[derive(Deserialize)]
+/// struct HasName(String);
+///
+/// #[derive(Deserialize)]
+/// struct PhysicBody {
+///     mass: u32,
+/// }
+/// # macro_rules! component {
+/// #     ($($ty:ty),*) => {$(
+/// #         impl Component for $ty {
+/// #             fn register_handlers(
+/// #                 builder: HandlerBuilder<Self>,
+/// #             ) -> HandlerBuilder<Self> {
+/// #                 builder
+/// #             }
+/// #         }
+/// #     )*};
+/// # }
+/// # component!(HasName, PhysicBody);
+///
+/// let mut fab = EntityFabricator::<()>::new();
+/// dialga::register_serde_all!(fab, {
+///     "has-name" => HasName,
+///     "physic-body" => PhysicBody,
+/// });
+/// # assert_eq!(fab.unused_assemblers().len(), 2);
+/// ```
+#[macro_export]
+macro_rules! register_serde_all {
+    ($fab:expr, { $($name:literal => $ty:ty),* $(,)? }) => {{
+        let fab = &mut $fab;
+        $(
+            fab.register_serde::<$ty>($name);
+        )*
+    }};
+}
+
+/// How much content is loaded into an [`EntityFabricator`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FabricatorStats {
+    /// How many blueprints there are.
+    pub blueprints: usize,
+    /// How many component nodes there are across all blueprints, not counting
+    /// the ones that come from splices.
+    pub components: usize,
+    /// The longest chain of splices in any blueprint, counting ones turned off
+    /// with `when=`.
+    pub max_inheritance_depth: usize,
+    /// Very rough guess of how many bytes the blueprints take up.
+    pub approx_memory: usize,
+}
+
 /// Things that can go wrong when instantiating an entity.
 #[derive(Debug, Error)]
 pub enum InstantiationError {
     #[error("while looking up the blueprint: {0}")]
     BlueprintLookupError(#[from] BlueprintLookupError),
-    #[error("there was no assembler registered for a component named {0:?}")]
-    NoAssembler(SmolStr),
+    /// The second field is the closest registered assembler name, if any are
+    /// close enough to likely be what was meant.
+    #[error(
+        "there was no assembler registered for a component named {0:?}{}",
+        did_you_mean(.1)
+    )]
+    NoAssembler(SmolStr, Option<SmolStr>),
     #[error("the assembler for {0:?} gave an error: {1}")]
     AssemblerError(SmolStr, eyre::Error),
 }
+
+fn did_you_mean(suggestion: &Option<SmolStr>) -> String {
+    match suggestion {
+        Some(name) => format!("; did you mean {:?}?", name),
+        None => String::new(),
+    }
+}
+
+/// How many single-character insertions, deletions or substitutions it takes
+/// to turn one string into the other.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diag = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = if ca == *cb {
+                diag
+            } else {
+                1 + diag.min(above).min(row[j])
+            };
+            diag = above;
+        }
+    }
+    row[b.len()]
+}
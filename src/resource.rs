@@ -0,0 +1,57 @@
+//! Long-lived shared state for assemblers.
+
+use std::any::{Any, TypeId};
+
+use ahash::AHashMap;
+
+/// A map holding at most one value of each type, stored in an
+/// [`EntityFabricator`] and lent out to every assembler while instantiating.
+///
+/// This is for things like asset tables and string interners that every
+/// instantiation shares, so they don't have to be threaded through `Ctx`.
+/// Assemblers only ever get a shared reference to it, so anything that needs
+/// to change while instantiating has to use interior mutability; otherwise,
+/// change it between instantiations with
+/// [`EntityFabricator::resources_mut`].
+///
+/// [`EntityFabricator`]: crate::EntityFabricator
+/// [`EntityFabricator::resources_mut`]: crate::EntityFabricator::resources_mut
+#[derive(Default)]
+pub struct Resources {
+    map: AHashMap<TypeId, Box<dyn Any + Send + Sync>>,
+}
+
+impl Resources {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Insert a resource, returning the old one of the same type if there
+    /// was one.
+    pub fn insert<R: Any + Send + Sync>(&mut self, resource: R) -> Option<R> {
+        self.map
+            .insert(TypeId::of::<R>(), Box::new(resource))
+            .map(|old| *old.downcast::<R>().unwrap())
+    }
+
+    /// Get the resource of the given type.
+    pub fn get<R: Any + Send + Sync>(&self) -> Option<&R> {
+        self.map
+            .get(&TypeId::of::<R>())
+            .map(|it| it.downcast_ref::<R>().unwrap())
+    }
+
+    /// Get the resource of the given type mutably.
+    pub fn get_mut<R: Any + Send + Sync>(&mut self) -> Option<&mut R> {
+        self.map
+            .get_mut(&TypeId::of::<R>())
+            .map(|it| it.downcast_mut::<R>().unwrap())
+    }
+
+    /// Remove the resource of the given type and return it.
+    pub fn remove<R: Any + Send + Sync>(&mut self) -> Option<R> {
+        self.map
+            .remove(&TypeId::of::<R>())
+            .map(|old| *old.downcast::<R>().unwrap())
+    }
+}
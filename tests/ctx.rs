@@ -1,4 +1,7 @@
-use dialga::{factory::ComponentFactory, EntityFabricator};
+use dialga::{
+    factory::{Assembly, ComponentFactory},
+    EntityFabricator,
+};
 use kdl::KdlNode;
 use palkia::prelude::*;
 
@@ -155,3 +158,158 @@ gamma {
     assert_eq!(gamma_foo, 69);
     assert_eq!(gamma_bar.as_str(), "beep boop");
 }
+
+#[test]
+fn conditional() {
+    let bp_src = r#"
+base {
+    single increment=1 foo=1
+}
+gated {
+    (splice)base
+    double-once-warm increment=10 bar="warm"
+}
+    "#;
+
+    let context = Context {
+        counter: AtomicU32::new(0),
+    };
+
+    let (mut world, mut fab) = setup_both();
+    fab.register_when(
+        "double-once-warm",
+        DoubleIncAssembler,
+        |ctx: &Context| ctx.counter.load(Ordering::SeqCst) >= 3,
+    );
+    fab.load_str(bp_src, "example.kdl")
+        .unwrap_or_else(|e| panic!("{:?}", miette::Report::new(e)));
+
+    // The counter is bumped by `single` before the gated node is checked,
+    // so it's 1 and then 2 here
+    for _ in 0..2 {
+        let cold = fab.instantiate("gated", world.spawn(), &context).unwrap();
+        assert!(world.query::<&DoubleInc>(cold).is_none());
+    }
+    assert_eq!(context.counter.load(Ordering::SeqCst), 2);
+
+    let warm = fab.instantiate("gated", world.spawn(), &context).unwrap();
+    assert_eq!(world.query::<&DoubleInc>(warm).unwrap().bar, "warm");
+    assert_eq!(context.counter.load(Ordering::SeqCst), 3 + 2 * 10);
+}
+
+struct MutContext {
+    next_id: u32,
+}
+
+struct HasId(u32);
+impl_component!(HasId);
+
+/// Hands out ids from the context, so it needs it mutably.
+struct IdAssembler;
+impl ComponentFactory<MutContext> for IdAssembler {
+    fn assemble<'a, 'w>(
+        &self,
+        _builder: EntityBuilder<'a, 'w>,
+        _node: &KdlNode,
+        _ctx: &MutContext,
+    ) -> eyre::Result<EntityBuilder<'a, 'w>> {
+        eyre::bail!("ids can only be handed out with a mutable context")
+    }
+
+    fn assemble_mut<'a, 'w>(
+        &self,
+        mut builder: EntityBuilder<'a, 'w>,
+        _node: &KdlNode,
+        ctx: &mut MutContext,
+    ) -> eyre::Result<EntityBuilder<'a, 'w>> {
+        builder.insert(HasId(ctx.next_id));
+        ctx.next_id += 1;
+        Ok(builder)
+    }
+}
+
+#[test]
+fn mutable_context() {
+    let mut world = World::new();
+    world.register_component::<HasId>();
+
+    let mut fab = EntityFabricator::new();
+    fab.register("id", IdAssembler);
+    fab.load_str("thing { id; }", "example.kdl")
+        .unwrap_or_else(|e| panic!("{:?}", miette::Report::new(e)));
+
+    let mut ctx = MutContext { next_id: 10 };
+    for expected in 10..13 {
+        let thing = fab
+            .instantiate_mut("thing", world.spawn(), &mut ctx)
+            .unwrap();
+        assert_eq!(world.query::<&HasId>(thing).unwrap().0, expected);
+    }
+    assert_eq!(ctx.next_id, 13);
+
+    assert!(fab.instantiate("thing", world.spawn(), &ctx).is_err());
+}
+
+/// Shared by every instantiation, so it lives in the fabricator's resources.
+struct ItemNames(Vec<&'static str>);
+
+struct ItemName(&'static str);
+impl_component!(ItemName);
+
+/// Looks the item's name up by index in the `ItemNames` resource.
+struct ItemNameAssembler;
+impl ComponentFactory<()> for ItemNameAssembler {
+    fn assemble<'a, 'w>(
+        &self,
+        _builder: EntityBuilder<'a, 'w>,
+        _node: &KdlNode,
+        _ctx: &(),
+    ) -> eyre::Result<EntityBuilder<'a, 'w>> {
+        eyre::bail!("item names need resources")
+    }
+
+    fn assemble_with<'a, 'w>(
+        &self,
+        mut builder: EntityBuilder<'a, 'w>,
+        node: &KdlNode,
+        assembly: &mut Assembly<'_, ()>,
+    ) -> eyre::Result<EntityBuilder<'a, 'w>> {
+        let idx: usize = knurdy::deserialize_node(node)?;
+        let names = assembly
+            .resources()
+            .get::<ItemNames>()
+            .ok_or_else(|| eyre::eyre!("no item names loaded"))?;
+        let name = names
+            .0
+            .get(idx)
+            .ok_or_else(|| eyre::eyre!("no item name #{}", idx))?;
+        builder.insert(ItemName(name));
+        Ok(builder)
+    }
+}
+
+#[test]
+fn shared_resources() {
+    let mut world = World::new();
+    world.register_component::<ItemName>();
+
+    let mut fab = EntityFabricator::new();
+    fab.register("item-name", ItemNameAssembler);
+    fab.load_str("sword { item-name 1; }", "example.kdl")
+        .unwrap_or_else(|e| panic!("{:?}", miette::Report::new(e)));
+
+    assert!(fab.instantiate("sword", world.spawn(), &()).is_err());
+
+    fab.insert_resource(ItemNames(vec!["Dagger", "Sword"]));
+    let sword = fab.instantiate("sword", world.spawn(), &()).unwrap();
+    assert_eq!(world.query::<&ItemName>(sword).unwrap().0, "Sword");
+
+    fab.resources_mut().get_mut::<ItemNames>().unwrap().0[1] = "Longsword";
+    let sword = fab.instantiate("sword", world.spawn(), &()).unwrap();
+    assert_eq!(world.query::<&ItemName>(sword).unwrap().0, "Longsword");
+    // Resources are handed out with a mutable context too
+    let sword = fab
+        .instantiate_mut("sword", world.spawn(), &mut ())
+        .unwrap();
+    assert_eq!(world.query::<&ItemName>(sword).unwrap().0, "Longsword");
+}
@@ -0,0 +1,49 @@
+use dialga::{factory::ComponentFactory, EntityFabricator};
+use kdl::KdlNode;
+use palkia::prelude::*;
+
+/// Remembers which entity it was put on.
+struct SelfAware {
+    me: Entity,
+}
+
+impl Component for SelfAware {
+    fn register_handlers(builder: HandlerBuilder<Self>) -> HandlerBuilder<Self>
+    where
+        Self: Sized,
+    {
+        builder
+    }
+}
+
+struct SelfAwareFactory;
+
+impl ComponentFactory<()> for SelfAwareFactory {
+    fn assemble<'a, 'w>(
+        &self,
+        mut builder: EntityBuilder<'a, 'w>,
+        _node: &KdlNode,
+        _ctx: &(),
+    ) -> eyre::Result<EntityBuilder<'a, 'w>> {
+        let me = builder.entity;
+        builder.insert(SelfAware { me });
+        Ok(builder)
+    }
+}
+
+#[test]
+fn component_knows_its_entity() {
+    let mut world = World::new();
+    world.register_component::<SelfAware>();
+
+    let mut fab = EntityFabricator::new();
+    fab.register("self-aware", SelfAwareFactory);
+    fab.load_str("thinker { self-aware; }", "example.kdl")
+        .unwrap_or_else(|e| panic!("{:?}", miette::Report::new(e)));
+
+    for _ in 0..3 {
+        let thinker = fab.instantiate("thinker", world.spawn(), &()).unwrap();
+        let sa = world.query::<&SelfAware>(thinker).unwrap();
+        assert_eq!(sa.me, thinker);
+    }
+}
@@ -0,0 +1,157 @@
+#![cfg(feature = "tracing")]
+
+use std::{
+    fmt::Debug,
+    sync::{Arc, Mutex},
+};
+
+use dialga::{EntityFabricator, InstantiationError};
+use palkia::prelude::*;
+use serde::Deserialize;
+use tracing::{
+    field::{Field, Visit},
+    span, Event, Level, Metadata, Subscriber,
+};
+
+#[derive(Deserialize)]
+struct Named(String);
+
+impl Component for Named {
+    fn register_handlers(builder: HandlerBuilder<Self>) -> HandlerBuilder<Self>
+    where
+        Self: Sized,
+    {
+        builder
+    }
+}
+
+/// Remembers the level and message of every event.
+#[derive(Clone, Default)]
+struct Recorder(Arc<Mutex<Vec<(Level, String)>>>);
+
+struct Message(String);
+
+impl Visit for Message {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        if field.name() == "message" {
+            self.0 = format!("{:?}", value);
+        }
+    }
+}
+
+impl Subscriber for Recorder {
+    fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, _span: &span::Attributes<'_>) -> span::Id {
+        span::Id::from_u64(1)
+    }
+
+    fn record(&self, _span: &span::Id, _values: &span::Record<'_>) {}
+
+    fn record_follows_from(&self, _span: &span::Id, _follows: &span::Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut message = Message(String::new());
+        event.record(&mut message);
+        self.0
+            .lock()
+            .unwrap()
+            .push((*event.metadata().level(), message.0));
+    }
+
+    fn enter(&self, _span: &span::Id) {}
+
+    fn exit(&self, _span: &span::Id) {}
+}
+
+#[test]
+fn returned_errors_are_not_logged_as_errors() {
+    let bp_src = r#"
+    fred {
+        name "Fred"
+    }
+    typo {
+        nmae "Fred"
+    }
+    "#;
+
+    let mut world = World::new();
+    world.register_component::<Named>();
+    let mut fab = EntityFabricator::<()>::new();
+    fab.register_serde::<Named>("name");
+    fab.load_str(bp_src, "example.kdl")
+        .unwrap_or_else(|e| panic!("{:?}", miette::Report::new(e)));
+
+    let recorder = Recorder::default();
+    tracing::subscriber::with_default(recorder.clone(), || {
+        let fred = fab.instantiate("fred", world.spawn(), &()).unwrap();
+        assert_eq!(world.query::<&Named>(fred).unwrap().0, "Fred");
+        assert!(matches!(
+            fab.instantiate("typo", world.spawn(), &()),
+            Err(InstantiationError::NoAssembler(..))
+        ));
+        assert!(matches!(
+            fab.instantiate("missing", world.spawn(), &()),
+            Err(InstantiationError::BlueprintLookupError(_))
+        ));
+    });
+
+    let events = recorder.0.lock().unwrap();
+    assert_eq!(
+        *events,
+        [
+            (Level::DEBUG, "assembled component".to_owned()),
+            (Level::DEBUG, "failed to assemble component".to_owned()),
+            (Level::DEBUG, "failed to resolve blueprint".to_owned()),
+        ]
+    );
+}
+
+#[test]
+fn overriding_a_spliced_component_warns() {
+    let bp_src = r#"
+    base {
+        name "Base"
+    }
+    child {
+        (splice)base
+        name "Child"
+    }
+    tagged {
+        tag "first"
+        tag "second"
+    }
+    "#;
+
+    let mut world = World::new();
+    world.register_component::<Named>();
+    let mut fab = EntityFabricator::<()>::new();
+    fab.register_serde::<Named>("name");
+    fab.register_serde::<Named>("tag");
+    fab.mark_repeatable("tag");
+    fab.load_str(bp_src, "example.kdl")
+        .unwrap_or_else(|e| panic!("{:?}", miette::Report::new(e)));
+
+    let recorder = Recorder::default();
+    tracing::subscriber::with_default(recorder.clone(), || {
+        let child = fab.instantiate("child", world.spawn(), &()).unwrap();
+        assert_eq!(world.query::<&Named>(child).unwrap().0, "Child");
+        // Repeatable components are meant to show up more than once
+        fab.instantiate("tagged", world.spawn(), &()).unwrap();
+    });
+
+    let events = recorder.0.lock().unwrap();
+    let warnings = events
+        .iter()
+        .filter(|(level, _)| *level == Level::WARN)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        warnings,
+        [&(
+            Level::WARN,
+            "component overrides an earlier one with the same name".to_owned()
+        )]
+    );
+}
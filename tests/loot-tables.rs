@@ -82,7 +82,7 @@ impl ComponentFactory<Context> for InventoryFactory {
                     let mut rng = ctx.rng.lock().unwrap();
                     table_picker.get(&mut *rng)
                 };
-                let e = ctx.fabber.instantiate(&bp_name, builder2, ctx)?;
+                let e = ctx.fabber.instantiate(bp_name, builder2, ctx)?;
                 vec![e]
             }
         };
@@ -0,0 +1,66 @@
+use dialga::{factory::ComponentFactory, EntityFabricator};
+use kdl::KdlNode;
+use palkia::prelude::*;
+
+/// Every `effect` node in the blueprint, in order.
+#[derive(Default)]
+struct Effects(Vec<String>);
+
+impl Component for Effects {
+    fn register_handlers(builder: HandlerBuilder<Self>) -> HandlerBuilder<Self>
+    where
+        Self: Sized,
+    {
+        builder
+    }
+}
+
+struct EffectFactory;
+
+impl ComponentFactory<()> for EffectFactory {
+    fn assemble<'a, 'w>(
+        &self,
+        mut builder: EntityBuilder<'a, 'w>,
+        node: &KdlNode,
+        _ctx: &(),
+    ) -> eyre::Result<EntityBuilder<'a, 'w>> {
+        let effect: String = knurdy::deserialize_node(node)?;
+        match builder.get_component_mut::<Effects>() {
+            Some(effects) => effects.0.push(effect),
+            None => {
+                builder.insert(Effects(vec![effect]));
+            }
+        }
+        Ok(builder)
+    }
+}
+
+#[test]
+fn repeatable_components() {
+    let bp_src = r#"
+    potion {
+        effect "glow"
+    }
+    poison {
+        (splice)potion
+        effect "nausea"
+        effect "damage"
+    }
+    poison {
+        effect "fizz"
+    }
+    "#;
+
+    let mut world = World::new();
+    world.register_component::<Effects>();
+
+    let mut fab = EntityFabricator::new();
+    fab.register("effect", EffectFactory);
+    fab.mark_repeatable("effect");
+    fab.load_str(bp_src, "example.kdl")
+        .unwrap_or_else(|e| panic!("{:?}", miette::Report::new(e)));
+
+    let poison = fab.instantiate("poison", world.spawn(), &()).unwrap();
+    let effects = world.query::<&Effects>(poison).unwrap();
+    assert_eq!(effects.0, ["glow", "nausea", "damage", "fizz"]);
+}
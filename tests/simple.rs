@@ -1,8 +1,21 @@
-use std::collections::HashMap;
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::Arc,
+};
 
-use dialga::EntityFabricator;
+use dialga::{
+    blueprint::{
+        BlueprintLibrary, BlueprintLookupError, BlueprintParseError,
+        LoadWarning, RawBlueprintDeserError, RawBlueprintParseErrorKind,
+    },
+    derived_component_name, EntityFabricator, Fabricator, FabricatorStats,
+    InstantiationError,
+};
+use kdl::{KdlDocument, KdlEntry, KdlNode};
+use miette::NamedSource;
 use palkia::prelude::*;
 use serde::Deserialize;
+use smol_str::SmolStr;
 
 macro_rules! impl_component {
     (@ $ty:ty) => {
@@ -55,7 +68,7 @@ struct FactionAffiliations {
     disliked_by: Vec<String>,
 }
 
-#[derive(Debug, PartialEq, Eq, Deserialize)]
+#[derive(Debug, Default, PartialEq, Eq, Deserialize)]
 struct Legendary;
 
 impl_component!(
@@ -248,7 +261,7 @@ fn error_unknown_blueprint() {
 }
 
 #[test]
-#[should_panic(expected = r#"NoAssembler("erroring-comp")"#)]
+#[should_panic(expected = r#"NoAssembler("erroring-comp", None)"#)]
 fn error_unknown_component() {
     let (mut world, mut fab) = setup_both();
 
@@ -264,6 +277,33 @@ fn error_unknown_component() {
     fab.instantiate("oh-no", world.spawn(), &()).unwrap();
 }
 
+#[test]
+fn unknown_component_suggestion() {
+    let (mut world, mut fab) = setup_both();
+
+    let bp_src = r#"
+    typo {
+        tracks-position
+    }
+    "#;
+    fab.load_str(bp_src, "example.kdl")
+        .unwrap_or_else(|e| panic!("{:?}", miette::Report::new(e)));
+
+    let err = fab.instantiate("typo", world.spawn(), &()).unwrap_err();
+    match &err {
+        InstantiationError::NoAssembler(name, suggestion) => {
+            assert_eq!(name, "tracks-position");
+            assert_eq!(suggestion.as_deref(), Some("tracked-position"));
+        }
+        err => panic!("{}", err),
+    }
+    assert_eq!(
+        err.to_string(),
+        "there was no assembler registered for a component named \
+         \"tracks-position\"; did you mean \"tracked-position\"?"
+    );
+}
+
 #[test]
 #[should_panic(expected = r#"BlueprintLookupError(InheritanceLoop(["#)]
 fn error_loop() {
@@ -298,7 +338,7 @@ fn error_loop() {
 
 #[test]
 #[should_panic(
-    expected = r#"BlueprintLookupError(InheriteeNotFound("foobar", "oh-no"))"#
+    expected = r#"BlueprintLookupError(InheriteeNotFound("foobar", "oh-no", []))"#
 )]
 fn error_splice_fail() {
     let bp_src = r#"
@@ -313,3 +353,1308 @@ fn error_splice_fail() {
 
     fab.instantiate("foobar", world.spawn(), &()).unwrap();
 }
+
+#[test]
+fn error_deep_splice_fail() {
+    let bp_src = r#"
+    entrypoint {
+        (splice)alpha
+    }
+    alpha {
+        (splice)beta
+    }
+    beta {
+        legendary
+        (splice)gamma
+    }
+    "#;
+
+    let mut fab = setup_fab();
+    fab.load_str(bp_src, "example.kdl")
+        .unwrap_or_else(|e| panic!("{:?}", miette::Report::new(e)));
+
+    let err = fab.inheritance_chain("entrypoint").unwrap_err();
+    assert_eq!(
+        err,
+        BlueprintLookupError::InheriteeNotFound(
+            "beta".into(),
+            "gamma".into(),
+            vec!["entrypoint".into(), "alpha".into()]
+        )
+    );
+    assert!(err
+        .to_string()
+        .contains("(via entrypoint -> alpha -> beta)"));
+
+    // Errors built by hand with no path still display fine
+    let err = BlueprintLookupError::InheriteeNotFound(
+        "beta".into(),
+        "gamma".into(),
+        vec![],
+    );
+    assert!(err.to_string().ends_with("(via beta)"));
+}
+
+#[test]
+fn unused_assemblers() {
+    let bp_src = r#"
+    mob {
+        tracked-position
+    }
+    grass {
+        physic-body mass=10
+        has-hp start-hp=10
+    }
+    weed {
+        (splice)grass
+        name "Dandelion"
+    }
+    "#;
+
+    let mut fab = setup_fab();
+    fab.load_str(bp_src, "example.kdl")
+        .unwrap_or_else(|e| panic!("{:?}", miette::Report::new(e)));
+
+    let unused = fab.unused_assemblers();
+    assert_eq!(
+        unused.iter().map(|s| s.as_str()).collect::<Vec<_>>(),
+        vec!["factions", "legendary"]
+    );
+}
+
+#[test]
+fn inheritance_chain() {
+    let bp_src = r#"
+    mob {
+        tracked-position
+    }
+    legend {
+        legendary
+    }
+    cat {
+        (splice)mob
+        physic-body mass=50
+    }
+    puma {
+        (splice)cat
+        physic-body mass=150
+        (splice)legend
+    }
+    "#;
+
+    let mut fab = setup_fab();
+    fab.load_str(bp_src, "example.kdl")
+        .unwrap_or_else(|e| panic!("{:?}", miette::Report::new(e)));
+
+    assert_eq!(fab.inheritance_chain("mob").unwrap(), Vec::<SmolStr>::new());
+    assert_eq!(
+        fab.inheritance_chain("puma").unwrap(),
+        ["cat", "mob", "legend"]
+    );
+}
+
+#[test]
+#[should_panic(expected = r#"InheritanceLoop(["#)]
+fn inheritance_chain_loop() {
+    let bp_src = r#"
+    alpha {
+        (splice)beta
+    }
+    beta {
+        (splice)alpha
+    }
+    "#;
+
+    let mut fab = setup_fab();
+    fab.load_str(bp_src, "example.kdl")
+        .unwrap_or_else(|e| panic!("{:?}", miette::Report::new(e)));
+
+    fab.inheritance_chain("alpha").unwrap();
+}
+
+#[test]
+fn instantiate_resolved() {
+    let bp_src = r#"
+    mob {
+        tracked-position
+    }
+    grass {
+        (splice)mob
+        physic-body mass=10
+        has-hp start-hp=10
+    }
+    "#;
+
+    let (mut world, mut fab) = setup_both();
+    fab.load_str(bp_src, "example.kdl")
+        .unwrap_or_else(|e| panic!("{:?}", miette::Report::new(e)));
+
+    let grass_print = fab.resolve("grass").unwrap();
+    assert_eq!(grass_print.components.len(), 3);
+
+    for _ in 0..5 {
+        let grass = fab
+            .instantiate_resolved(&grass_print, world.spawn(), &())
+            .unwrap();
+        let (pb, _tp) = world
+            .query::<(&PhysicBody, &TrackedPosition)>(grass)
+            .unwrap();
+        assert_eq!(*pb, PhysicBody { mass: 10 });
+    }
+}
+
+#[test]
+fn load_bytes_and_reader() {
+    let (mut world, mut fab) = setup_both();
+
+    let grass_src = "grass { physic-body mass=10; }";
+    fab.load_bytes(grass_src.as_bytes(), "grass.kdl")
+        .unwrap_or_else(|e| panic!("{:?}", miette::Report::new(e)));
+    let legend_src = "legend { legendary; }";
+    fab.load_reader(legend_src.as_bytes(), "legend.kdl")
+        .unwrap_or_else(|e| panic!("{:?}", miette::Report::new(e)));
+
+    let grass = fab.instantiate("grass", world.spawn(), &()).unwrap();
+    assert_eq!(
+        *world.query::<&PhysicBody>(grass).unwrap(),
+        PhysicBody { mass: 10 }
+    );
+    let legend = fab.instantiate("legend", world.spawn(), &()).unwrap();
+    assert!(world.query::<&Legendary>(legend).is_some());
+
+    let err = fab.load_bytes(b"oh-no { legendary \xff; }", "bad.kdl");
+    assert!(matches!(err, Err(BlueprintParseError::Utf8(_))));
+}
+
+#[test]
+fn stats() {
+    let bp_src = r#"
+    mob {
+        tracked-position
+    }
+    cat {
+        (splice)mob
+        physic-body mass=50
+    }
+    housecat {
+        (splice)cat
+        name "Macy"
+    }
+    grass {
+        physic-body mass=10
+        has-hp start-hp=10
+    }
+    "#;
+
+    let mut fab = setup_fab();
+    assert_eq!(fab.stats(), FabricatorStats::default());
+
+    fab.load_str(bp_src, "example.kdl")
+        .unwrap_or_else(|e| panic!("{:?}", miette::Report::new(e)));
+
+    let stats = fab.stats();
+    assert_eq!(stats.blueprints, 4);
+    assert_eq!(stats.components, 5);
+    assert_eq!(stats.max_inheritance_depth, 2);
+    assert!(stats.approx_memory > 0);
+
+    // Turned-off blueprints still count, whatever the flags are
+    fab.load_str(
+        r#"show-cat when="cat-show" { (splice)housecat; }"#,
+        "show.kdl",
+    )
+    .unwrap_or_else(|e| panic!("{:?}", miette::Report::new(e)));
+    assert_eq!(fab.stats().max_inheritance_depth, 3);
+    fab.set_flag("cat-show", true);
+    assert_eq!(fab.stats().max_inheritance_depth, 3);
+}
+
+#[test]
+fn load_strs_collects_errors() {
+    let (mut world, mut fab) = setup_both();
+
+    let err = fab
+        .load_strs([
+            ("grass { physic-body mass=10; }", "grass.kdl"),
+            ("oh-no", "no-children.kdl"),
+            ("legend { legendary; }", "legend.kdl"),
+            ("oh-no-again { (bad)annotation; }", "bad-annotation.kdl"),
+        ])
+        .unwrap_err();
+    assert_eq!(err.0.len(), 2);
+    assert_eq!(miette::Diagnostic::related(&err).unwrap().count(), 2);
+
+    // The good files still got loaded
+    fab.instantiate("grass", world.spawn(), &()).unwrap();
+    fab.instantiate("legend", world.spawn(), &()).unwrap();
+}
+
+#[test]
+fn marker_components() {
+    let bp_src = r#"
+    plain {
+        legendary
+    }
+    on {
+        legendary true
+    }
+    off {
+        legendary false
+    }
+    "#;
+
+    let mut world = setup_world();
+    let mut fab = EntityFabricator::<()>::new();
+    fab.register_marker::<Legendary>("legendary");
+    fab.load_str(bp_src, "example.kdl")
+        .unwrap_or_else(|e| panic!("{:?}", miette::Report::new(e)));
+
+    let plain = fab.instantiate("plain", world.spawn(), &()).unwrap();
+    assert!(world.query::<&Legendary>(plain).is_some());
+    let on = fab.instantiate("on", world.spawn(), &()).unwrap();
+    assert!(world.query::<&Legendary>(on).is_some());
+    let off = fab.instantiate("off", world.spawn(), &()).unwrap();
+    assert!(world.query::<&Legendary>(off).is_none());
+
+    fab.load_str("bad { legendary 5; }", "bad.kdl")
+        .unwrap_or_else(|e| panic!("{:?}", miette::Report::new(e)));
+    assert!(fab.instantiate("bad", world.spawn(), &()).is_err());
+
+    // Turning off a spliced-in marker isn't supported, so it's an error
+    // instead of quietly leaving it on
+    fab.load_str("humbled { (splice)plain; legendary false; }", "more.kdl")
+        .unwrap_or_else(|e| panic!("{:?}", miette::Report::new(e)));
+    assert!(matches!(
+        fab.instantiate("humbled", world.spawn(), &()),
+        Err(InstantiationError::AssemblerError(..))
+    ));
+    // Merging over the node does turn it off
+    fab.load_str("plain { legendary false; }", "more.kdl")
+        .unwrap_or_else(|e| panic!("{:?}", miette::Report::new(e)));
+    let plain = fab.instantiate("plain", world.spawn(), &()).unwrap();
+    assert!(world.query::<&Legendary>(plain).is_none());
+}
+
+#[test]
+fn file_scoped_splices() {
+    let (mut world, mut fab) = setup_both();
+
+    fab.load_str("mob { tracked-position; }", "mobs.kdl")
+        .unwrap_or_else(|e| panic!("{:?}", miette::Report::new(e)));
+    let cats_src = r#"
+    cat-base {
+        physic-body mass=50
+    }
+    housecat scope="file" {
+        (splice)cat-base
+        name "Macy"
+    }
+    alleycat scope="file" {
+        (splice)mob
+        (splice)cat-base
+    }
+    stray {
+        (splice)mob
+    }
+    "#;
+    fab.load_str(cats_src, "cats.kdl")
+        .unwrap_or_else(|e| panic!("{:?}", miette::Report::new(e)));
+
+    fab.instantiate("housecat", world.spawn(), &()).unwrap();
+    fab.instantiate("stray", world.spawn(), &()).unwrap();
+    assert_eq!(
+        fab.inheritance_chain("alleycat").unwrap_err(),
+        BlueprintLookupError::InheriteeOutOfScope(
+            "alleycat".into(),
+            "mob".into(),
+            vec![]
+        )
+    );
+
+    let err = fab
+        .load_str(r#"oops scope="galaxy" { legendary; }"#, "bad.kdl")
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        BlueprintParseError::Deser(RawBlueprintDeserError {
+            kind: RawBlueprintParseErrorKind::BadScope,
+            ..
+        })
+    ));
+}
+
+#[test]
+fn load_document() {
+    let (mut world, mut fab) = setup_both();
+
+    let src = "grass { physic-body mass=10; }";
+    let doc: KdlDocument = src.parse().unwrap();
+    let named = Arc::new(NamedSource::new("grass.kdl", src.to_owned()));
+    fab.load_document(&doc, named.clone())
+        .unwrap_or_else(|e| panic!("{:?}", miette::Report::new(e)));
+    // The library doesn't hang on to the source once it's loaded
+    assert_eq!(Arc::strong_count(&named), 1);
+
+    let grass = fab.instantiate("grass", world.spawn(), &()).unwrap();
+    assert_eq!(
+        *world.query::<&PhysicBody>(grass).unwrap(),
+        PhysicBody { mass: 10 }
+    );
+}
+
+#[test]
+fn resolved_order() {
+    let bp_src = r#"
+    base {
+        physic-body mass=1
+        has-hp start-hp=1
+    }
+    middle {
+        legendary
+        (splice)base
+        name "Middle"
+    }
+    splice-first {
+        (splice)middle
+        tracked-position
+    }
+    splice-last {
+        tracked-position
+        (splice)middle
+    }
+    "#;
+
+    let mut fab = setup_fab();
+    fab.load_str(bp_src, "example.kdl")
+        .unwrap_or_else(|e| panic!("{:?}", miette::Report::new(e)));
+
+    let names = |fab: &EntityFabricator<()>, bp: &str| {
+        fab.resolve(bp)
+            .unwrap()
+            .components
+            .iter()
+            .map(|node| node.name().value().to_owned())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        names(&fab, "splice-first"),
+        [
+            "legendary",
+            "physic-body",
+            "has-hp",
+            "name",
+            "tracked-position"
+        ]
+    );
+    assert_eq!(
+        names(&fab, "splice-last"),
+        [
+            "tracked-position",
+            "legendary",
+            "physic-body",
+            "has-hp",
+            "name"
+        ]
+    );
+
+    // Merging replaces components in place and appends new ones at the end
+    fab.load_str(r#"middle { name "Changed"; factions; }"#, "overrides.kdl")
+        .unwrap_or_else(|e| panic!("{:?}", miette::Report::new(e)));
+    assert_eq!(
+        names(&fab, "middle"),
+        ["legendary", "physic-body", "has-hp", "name", "factions"]
+    );
+}
+
+#[test]
+fn clearing() {
+    let (mut world, mut fab) = setup_both();
+
+    fab.load_str("grass { physic-body mass=10; }", "grass.kdl")
+        .unwrap_or_else(|e| panic!("{:?}", miette::Report::new(e)));
+    fab.clear_blueprints();
+    assert!(fab.instantiate("grass", world.spawn(), &()).is_err());
+
+    // The assemblers are still around
+    fab.load_str("grass { physic-body mass=20; }", "grass.kdl")
+        .unwrap_or_else(|e| panic!("{:?}", miette::Report::new(e)));
+    let grass = fab.instantiate("grass", world.spawn(), &()).unwrap();
+    assert_eq!(
+        *world.query::<&PhysicBody>(grass).unwrap(),
+        PhysicBody { mass: 20 }
+    );
+
+    fab.register_deprecated_alias("mass", "physic-body");
+    fab.set_flag("hard-mode", true);
+    fab.insert_resource(7u32);
+    fab.clear_all();
+    assert_eq!(fab.stats().blueprints, 0);
+    assert!(fab.unused_assemblers().is_empty());
+    assert!(!fab.flag_enabled("hard-mode"));
+    assert!(fab.get_resource::<u32>().is_none());
+    // Registering the same name again doesn't panic any more
+    fab.register_serde::<PhysicBody>("physic-body");
+    // and the old alias is gone
+    fab.load_str("rock { mass mass=20; }", "rock.kdl")
+        .unwrap_or_else(|e| panic!("{:?}", miette::Report::new(e)));
+    assert!(matches!(
+        fab.instantiate("rock", world.spawn(), &()),
+        Err(InstantiationError::NoAssembler(..))
+    ));
+}
+
+#[test]
+fn diffing() {
+    let old_src = r#"
+    cat {
+        tracked-position
+        physic-body mass=50
+        has-hp start-hp=10
+        name "Macy"
+    }
+    "#;
+    let new_src = r#"
+    cat {
+        // Reformatted, but the same
+        tracked-position { }
+        physic-body   mass=0x32
+        has-hp {
+            start-hp 10
+        }
+        legendary
+    }
+    "#;
+
+    let mut old_fab = setup_fab();
+    old_fab
+        .load_str(old_src, "old.kdl")
+        .unwrap_or_else(|e| panic!("{:?}", miette::Report::new(e)));
+    let mut new_fab = setup_fab();
+    new_fab
+        .load_str(new_src, "new.kdl")
+        .unwrap_or_else(|e| panic!("{:?}", miette::Report::new(e)));
+
+    let old = old_fab.resolve("cat").unwrap();
+    let new = new_fab.resolve("cat").unwrap();
+    assert!(old.diff(&old).is_empty());
+
+    let diff = old.diff(&new);
+    let names = |nodes: &[KdlNode]| {
+        nodes
+            .iter()
+            .map(|node| node.name().value().to_owned())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(names(&diff.added), ["legendary"]);
+    assert_eq!(names(&diff.removed), ["name"]);
+    // Moving a property into a child is a real change
+    assert_eq!(diff.changed.len(), 1);
+    assert_eq!(diff.changed[0].old.name().value(), "has-hp");
+}
+
+#[test]
+fn factory_defaults() {
+    let bp_src = r#"
+    rock {
+        physic-body
+    }
+    boulder {
+        physic-body mass=500
+    }
+    grass {
+        has-hp start-hp=3
+    }
+    moss {
+        has-hp start_hp=4
+    }
+    tree {
+        has-hp {
+            resistances fire=-50
+        }
+    }
+    "#;
+
+    let mut world = setup_world();
+    let mut fab = EntityFabricator::<()>::new();
+    fab.register_serde_with_defaults::<PhysicBody>(
+        "physic-body",
+        "physic-body mass=1".parse().unwrap(),
+    );
+    fab.register_serde_with_defaults::<HasHP>(
+        "has-hp",
+        "has-hp { start-hp 10; resistances cold=5; }"
+            .parse()
+            .unwrap(),
+    );
+    fab.load_str(bp_src, "example.kdl")
+        .unwrap_or_else(|e| panic!("{:?}", miette::Report::new(e)));
+
+    let rock = fab.instantiate("rock", world.spawn(), &()).unwrap();
+    assert_eq!(
+        *world.query::<&PhysicBody>(rock).unwrap(),
+        PhysicBody { mass: 1 }
+    );
+    let boulder = fab.instantiate("boulder", world.spawn(), &()).unwrap();
+    assert_eq!(
+        *world.query::<&PhysicBody>(boulder).unwrap(),
+        PhysicBody { mass: 500 }
+    );
+
+    let grass = fab.instantiate("grass", world.spawn(), &()).unwrap();
+    assert_eq!(
+        *world.query::<&HasHP>(grass).unwrap(),
+        HasHP {
+            start_hp: 3,
+            resistances: [("cold".to_string(), 5)].into_iter().collect(),
+        }
+    );
+    // The defaults say `start-hp`, but `start_hp` is the same field
+    let moss = fab.instantiate("moss", world.spawn(), &()).unwrap();
+    assert_eq!(world.query::<&HasHP>(moss).unwrap().start_hp, 4);
+    let tree = fab.instantiate("tree", world.spawn(), &()).unwrap();
+    assert_eq!(
+        *world.query::<&HasHP>(tree).unwrap(),
+        HasHP {
+            start_hp: 10,
+            resistances: [("fire".to_string(), -50)].into_iter().collect(),
+        }
+    );
+}
+
+#[test]
+fn splice_is_positional() {
+    let bp_src = r#"
+    heavy {
+        physic-body mass=1000
+    }
+    light {
+        physic-body mass=1
+    }
+    // Splicing after your own component means the spliced one wins ...
+    overridden {
+        physic-body mass=50
+        (splice)heavy
+    }
+    // ... and the same blueprint can be spliced in more than once.
+    flip-flop {
+        (splice)heavy
+        (splice)light
+        (splice)heavy
+    }
+    "#;
+
+    let (mut world, mut fab) = setup_both();
+    fab.load_str(bp_src, "example.kdl")
+        .unwrap_or_else(|e| panic!("{:?}", miette::Report::new(e)));
+
+    let overridden = fab.instantiate("overridden", world.spawn(), &()).unwrap();
+    assert_eq!(
+        *world.query::<&PhysicBody>(overridden).unwrap(),
+        PhysicBody { mass: 1000 }
+    );
+
+    assert_eq!(
+        fab.inheritance_chain("flip-flop").unwrap(),
+        ["heavy", "light", "heavy"]
+    );
+    let flip_flop = fab.instantiate("flip-flop", world.spawn(), &()).unwrap();
+    assert_eq!(
+        *world.query::<&PhysicBody>(flip_flop).unwrap(),
+        PhysicBody { mass: 1000 }
+    );
+}
+
+#[test]
+fn diamond_splices_once() {
+    let bp_src = r#"
+    creature {
+        physic-body mass=50
+        has-hp start-hp=10
+    }
+    big {
+        (splice)creature
+        physic-body mass=1000
+    }
+    flying {
+        (splice)creature
+        has-hp start-hp=5
+    }
+    dragon {
+        (splice)big
+        (splice)flying
+    }
+    "#;
+
+    let (mut world, mut fab) = setup_both();
+    fab.load_str(bp_src, "example.kdl")
+        .unwrap_or_else(|e| panic!("{:?}", miette::Report::new(e)));
+
+    assert_eq!(
+        fab.inheritance_chain("dragon").unwrap(),
+        ["big", "creature", "flying"]
+    );
+    // `creature` comes in once, ahead of both of its inheritors, so splicing
+    // `flying` doesn't put its mass back.
+    let dragon = fab.resolve("dragon").unwrap();
+    assert_eq!(dragon.components.len(), 4);
+    let dragon = fab.instantiate("dragon", world.spawn(), &()).unwrap();
+    assert_eq!(
+        *world.query::<&PhysicBody>(dragon).unwrap(),
+        PhysicBody { mass: 1000 }
+    );
+    assert_eq!(world.query::<&HasHP>(dragon).unwrap().start_hp, 5);
+}
+
+#[test]
+fn reserved_names() {
+    let mut fab = setup_fab();
+    for (src, name) in [
+        ("inherit { legendary; }", "inherit"),
+        ("splice { legendary; }", "splice"),
+        ("foo { splice \"bar\"; }", "splice"),
+        ("foo { legendary; inherit \"bar\"; }", "inherit"),
+    ] {
+        let err = fab.load_str(src, "reserved.kdl").unwrap_err();
+        match err {
+            BlueprintParseError::Deser(RawBlueprintDeserError {
+                kind: RawBlueprintParseErrorKind::ReservedName(found),
+                ..
+            }) => assert_eq!(found, name),
+            err => panic!("{:?}", miette::Report::new(err)),
+        }
+    }
+}
+
+#[test]
+fn flags() {
+    let bp_src = r#"
+    debug-gear when="debug" {
+        legendary
+    }
+    featherweight when="!heavy-mode" {
+        physic-body mass=1
+    }
+    dummy {
+        physic-body mass=100
+        (splice)featherweight
+        (splice)debug-gear
+    }
+    "#;
+
+    let (mut world, mut fab) = setup_both();
+    fab.load_str(bp_src, "example.kdl")
+        .unwrap_or_else(|e| panic!("{:?}", miette::Report::new(e)));
+
+    assert!(!fab.flag_enabled("debug"));
+    assert_eq!(
+        fab.inheritance_chain("debug-gear").unwrap_err(),
+        BlueprintLookupError::BlueprintNotFound("debug-gear".into())
+    );
+    assert_eq!(fab.inheritance_chain("dummy").unwrap(), ["featherweight"]);
+    let dummy = fab.instantiate("dummy", world.spawn(), &()).unwrap();
+    assert_eq!(
+        *world.query::<&PhysicBody>(dummy).unwrap(),
+        PhysicBody { mass: 1 }
+    );
+    assert!(world.query::<&Legendary>(dummy).is_none());
+
+    fab.set_flag("debug", true);
+    fab.set_flag("heavy-mode", true);
+    assert_eq!(fab.inheritance_chain("dummy").unwrap(), ["debug-gear"]);
+    let dummy = fab.instantiate("dummy", world.spawn(), &()).unwrap();
+    assert_eq!(
+        *world.query::<&PhysicBody>(dummy).unwrap(),
+        PhysicBody { mass: 100 }
+    );
+    assert!(world.query::<&Legendary>(dummy).is_some());
+
+    let err = fab
+        .load_str(r#"oops when="" { legendary; }"#, "bad.kdl")
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        BlueprintParseError::Deser(RawBlueprintDeserError {
+            kind: RawBlueprintParseErrorKind::BadWhen,
+            ..
+        })
+    ));
+}
+
+#[test]
+fn erased_fabricator() {
+    let (mut world, mut fab) = setup_both();
+    fab.load_str("grass { physic-body mass=10; }", "grass.kdl")
+        .unwrap_or_else(|e| panic!("{:?}", miette::Report::new(e)));
+
+    let fab: Box<dyn Fabricator> = Box::new(fab);
+    let grass = fab.instantiate_erased("grass", world.spawn()).unwrap();
+    assert_eq!(
+        *world.query::<&PhysicBody>(grass).unwrap(),
+        PhysicBody { mass: 10 }
+    );
+}
+
+#[test]
+fn descendants_of() {
+    let bp_src = r#"
+    creature {
+        legendary
+    }
+    animal {
+        (splice)creature
+    }
+    dog {
+        (splice)animal
+    }
+    golem {
+        (splice)creature
+    }
+    rock {
+        physic-body mass=10
+    }
+    ouroboros {
+        (splice)creature
+        (splice)ouroboros
+    }
+    debug-dummy when="debug" {
+        (splice)creature
+    }
+    "#;
+
+    let mut fab = setup_fab();
+    fab.load_str(bp_src, "example.kdl")
+        .unwrap_or_else(|e| panic!("{:?}", miette::Report::new(e)));
+
+    // `debug` is off, but debug-dummy still depends on creature
+    assert_eq!(
+        fab.descendants_of("creature"),
+        ["animal", "debug-dummy", "dog", "golem", "ouroboros"]
+    );
+    assert_eq!(fab.descendants_of("animal"), ["dog"]);
+    assert!(fab.descendants_of("rock").is_empty());
+    assert!(fab.descendants_of("nonexistent").is_empty());
+}
+
+#[test]
+fn clobber_merge() {
+    let mut fab = setup_fab();
+    let err = fab
+        .load_str(
+            r#"oops merge="merge" merge="clobber" { legendary; }"#,
+            "bad.kdl",
+        )
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        BlueprintParseError::Deser(RawBlueprintDeserError {
+            kind: RawBlueprintParseErrorKind::ClobberMerge,
+            ..
+        })
+    ));
+}
+
+#[test]
+fn migrations() {
+    let (mut world, mut fab) = setup_both();
+    // Version 1 called it `hp`; version 2 renamed it to `start_hp`
+    fab.register_migration("has-hp", 1, |node| {
+        for entry in node.entries_mut() {
+            if entry.name().map(|key| key.value()) == Some("hp") {
+                *entry = KdlEntry::new_prop("start_hp", entry.value().clone());
+            }
+        }
+    });
+    // Version 2 had the mass in tons
+    fab.register_migration("physic-body", 2, |node| {
+        if let Some(entry) = node.get_mut("mass") {
+            let tons = entry.value().as_i64().unwrap();
+            entry.set_value(tons * 1000);
+        }
+    });
+
+    let bp_src = r#"
+    old-golem {
+        has-hp hp=50
+        physic-body mass=2
+    }
+    newer-golem schema=2 {
+        has-hp start_hp=60
+        physic-body mass=2
+    }
+    newest-golem schema=3 {
+        has-hp start_hp=70
+        physic-body mass=2000
+    }
+    "#;
+    fab.load_str(bp_src, "example.kdl")
+        .unwrap_or_else(|e| panic!("{:?}", miette::Report::new(e)));
+
+    for (name, hp) in
+        [("old-golem", 50), ("newer-golem", 60), ("newest-golem", 70)]
+    {
+        let golem = fab.instantiate(name, world.spawn(), &()).unwrap();
+        let (body, health) =
+            world.query::<(&PhysicBody, &HasHP)>(golem).unwrap();
+        assert_eq!(*body, PhysicBody { mass: 2000 }, "{}", name);
+        assert_eq!(health.start_hp, hp, "{}", name);
+    }
+
+    let err = fab
+        .load_str(r#"oops schema=-1 { legendary; }"#, "bad.kdl")
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        BlueprintParseError::Deser(RawBlueprintDeserError {
+            kind: RawBlueprintParseErrorKind::BadSchema,
+            ..
+        })
+    ));
+}
+
+#[test]
+fn iter_raw() {
+    let bp_src = r#"
+    grass {
+        physic-body mass=10
+    }
+    tree {
+        physic-body mass=500
+    }
+    oops "argument" {
+        legendary
+    }
+    "#;
+
+    let (mut world, mut fab) = setup_both();
+    let mut raws = BlueprintLibrary::iter_raw_from_str(bp_src, "example.kdl")
+        .unwrap_or_else(|e| panic!("{:?}", miette::Report::new(e)));
+
+    let grass = raws.next().unwrap().unwrap();
+    assert_eq!(grass.name(), "grass");
+    fab.insert_raw(grass);
+    // Skip the tree
+    raws.next().unwrap().unwrap();
+    assert!(matches!(
+        raws.next(),
+        Some(Err(RawBlueprintDeserError {
+            kind: RawBlueprintParseErrorKind::TopLevelArgument,
+            ..
+        }))
+    ));
+    assert!(raws.next().is_none());
+
+    fab.instantiate("grass", world.spawn(), &()).unwrap();
+    assert!(fab.instantiate("tree", world.spawn(), &()).is_err());
+}
+
+#[test]
+fn errors_share_source() {
+    let bp_src = r#"
+    bad-one "argument" {
+        legendary
+    }
+    bad-two
+    "#;
+
+    let errors = BlueprintLibrary::iter_raw_from_str(bp_src, "bad.kdl")
+        .unwrap_or_else(|e| panic!("{:?}", miette::Report::new(e)))
+        .map(|raw| raw.err().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(errors.len(), 2);
+    assert!(Arc::ptr_eq(&errors[0].src, &errors[1].src));
+
+    // The shared source still gets rendered.
+    for err in errors {
+        let report = format!("{:?}", miette::Report::new(err));
+        assert!(report.contains("bad.kdl"), "{}", report);
+    }
+}
+
+#[test]
+fn fabricate() {
+    let (mut world, mut fab) = setup_both();
+    fab.load_str("grass { physic-body mass=10; }", "grass.kdl")
+        .unwrap_or_else(|e| panic!("{:?}", miette::Report::new(e)));
+
+    let grass = fab
+        .fabricate("grass", &mut world, &())
+        .unwrap()
+        .with(Named("Bluegrass".to_owned()))
+        .build();
+    let (body, name) = world.query::<(&PhysicBody, &Named)>(grass).unwrap();
+    assert_eq!(*body, PhysicBody { mass: 10 });
+    assert_eq!(name.0, "Bluegrass");
+}
+
+#[test]
+fn prefilled_builder() {
+    let (mut world, mut fab) = setup_both();
+    fab.load_str("grass { physic-body mass=10; }", "grass.kdl")
+        .unwrap_or_else(|e| panic!("{:?}", miette::Report::new(e)));
+
+    let builder = world
+        .spawn()
+        .with(Named("Bluegrass".to_owned()))
+        .with(PhysicBody { mass: 99 });
+    let grass = fab.instantiate("grass", builder, &()).unwrap();
+    let (body, name) = world.query::<(&PhysicBody, &Named)>(grass).unwrap();
+    // The blueprint's component wins ...
+    assert_eq!(*body, PhysicBody { mass: 10 });
+    // ... and everything else is kept
+    assert_eq!(name.0, "Bluegrass");
+}
+
+#[test]
+fn validate_one() {
+    let bp_src = r#"
+    grass {
+        physic-body mass=10
+        legendary
+    }
+    broken {
+        physic-body mass="heavy"
+        legendary "yes"
+        has-hp start_hp=10
+        flavor-text "nobody registered this"
+    }
+    "#;
+
+    let mut fab = setup_fab();
+    fab.load_str(bp_src, "example.kdl")
+        .unwrap_or_else(|e| panic!("{:?}", miette::Report::new(e)));
+
+    fab.validate_one("grass", &()).unwrap();
+
+    let errs = fab.validate_one("broken", &()).unwrap_err();
+    let summary = errs
+        .iter()
+        .map(|err| match err {
+            InstantiationError::AssemblerError(name, _) => {
+                format!("bad {}", name)
+            }
+            InstantiationError::NoAssembler(name, _) => {
+                format!("missing {}", name)
+            }
+            err => panic!("{}", err),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        summary,
+        ["bad physic-body", "bad legendary", "missing flavor-text"]
+    );
+
+    // Validating agrees with instantiating about aliases and typos
+    fab.register_deprecated_alias("hp", "has-hp");
+    fab.load_str("golem { hp start_hp=50; tracks-position; }", "golem.kdl")
+        .unwrap_or_else(|e| panic!("{:?}", miette::Report::new(e)));
+    let errs = fab.validate_one("golem", &()).unwrap_err();
+    match errs.as_slice() {
+        [InstantiationError::NoAssembler(name, suggestion)] => {
+            assert_eq!(name, "tracks-position");
+            assert_eq!(suggestion.as_deref(), Some("tracked-position"));
+        }
+        _ => panic!("{:?}", errs),
+    }
+
+    let errs = fab.validate_one("nonexistent", &()).unwrap_err();
+    assert!(matches!(
+        errs.as_slice(),
+        [InstantiationError::BlueprintLookupError(
+            BlueprintLookupError::BlueprintNotFound(_)
+        )]
+    ));
+}
+
+#[test]
+fn unit_components() {
+    // Several markers can go on one line, separated by semicolons
+    let bp_src = r#"
+    relic {
+        legendary; tracked-position
+        physic-body mass=3
+    }
+    bad-relic {
+        tracked-position
+        legendary "oops"
+    }
+    "#;
+
+    let (mut world, mut fab) = setup_both();
+    fab.load_str(bp_src, "example.kdl")
+        .unwrap_or_else(|e| panic!("{:?}", miette::Report::new(e)));
+
+    let relic = fab.instantiate("relic", world.spawn(), &()).unwrap();
+    assert!(world
+        .query::<(&Legendary, &TrackedPosition, &PhysicBody)>(relic)
+        .is_some());
+
+    let err = fab
+        .instantiate("bad-relic", world.spawn(), &())
+        .unwrap_err();
+    assert!(matches!(
+        &err,
+        InstantiationError::AssemblerError(name, _) if name == "legendary"
+    ));
+    assert!(err.to_string().contains(r#""legendary""#));
+}
+
+#[test]
+fn duplicate_warnings() {
+    let bp_src = r#"
+    cat {
+        physic-body mass=5
+        legendary
+        physic-body mass=6
+    }
+    dog {
+        physic-body mass=20
+    }
+    "#;
+
+    let (mut world, mut fab) = setup_both();
+    fab.load_str(bp_src, "quiet.kdl")
+        .unwrap_or_else(|e| panic!("{:?}", miette::Report::new(e)));
+    assert!(fab.take_warnings().is_empty());
+
+    fab.set_warn_duplicates(true);
+    fab.load_str(bp_src, "loud.kdl")
+        .unwrap_or_else(|e| panic!("{:?}", miette::Report::new(e)));
+    let warnings = fab.take_warnings();
+    let warning = match warnings.as_slice() {
+        [LoadWarning::DuplicateComponent(it)] => it,
+        _ => panic!("{:?}", warnings),
+    };
+    assert_eq!(warning.blueprint, "cat");
+    assert_eq!(warning.component, "physic-body");
+    assert_eq!(warning.src.name(), "loud.kdl");
+    assert_eq!(
+        &bp_src[warning.span.offset()..][..warning.span.len()].trim(),
+        &"physic-body mass=6"
+    );
+    assert!(fab.take_warnings().is_empty());
+
+    // It's still just a warning; the later one wins
+    let cat = fab.instantiate("cat", world.spawn(), &()).unwrap();
+    assert_eq!(
+        *world.query::<&PhysicBody>(cat).unwrap(),
+        PhysicBody { mass: 6 }
+    );
+}
+
+#[test]
+fn duplicate_warnings_after_migration() {
+    // Version 1 called it `mass`
+    let bp_src = r#"
+    boulder {
+        mass mass=100
+        physic-body mass=200
+        physic-body mass=300
+    }
+    "#;
+
+    let mut fab = setup_fab();
+    fab.register_migration("mass", 1, |node| node.set_name("physic-body"));
+    fab.set_warn_duplicates(true);
+    // Going through insert_raw warns just the same
+    for raw in BlueprintLibrary::iter_raw_from_str(bp_src, "boulder.kdl")
+        .unwrap_or_else(|e| panic!("{:?}", miette::Report::new(e)))
+    {
+        fab.insert_raw(raw.unwrap());
+    }
+
+    // Each copy is pointed at the one just before it
+    let text = |span: miette::SourceSpan| {
+        bp_src[span.offset()..][..span.len()].trim().to_owned()
+    };
+    let pairs = fab
+        .take_warnings()
+        .into_iter()
+        .map(|warning| match warning {
+            LoadWarning::DuplicateComponent(it) => {
+                (text(it.previous), text(it.span))
+            }
+            warning => panic!("{:?}", warning),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        pairs,
+        [
+            (
+                "mass mass=100".to_owned(),
+                "physic-body mass=200".to_owned()
+            ),
+            (
+                "physic-body mass=200".to_owned(),
+                "physic-body mass=300".to_owned()
+            ),
+        ]
+    );
+}
+
+#[test]
+fn derived_names() {
+    assert_eq!(derived_component_name::<HasHP>(), "has-hp");
+    assert_eq!(derived_component_name::<PhysicBody>(), "physic-body");
+    assert_eq!(derived_component_name::<Legendary>(), "legendary");
+    assert_eq!(derived_component_name::<Vec<PhysicBody>>(), "vec");
+
+    let (mut world, _) = setup_both();
+    let mut fab = EntityFabricator::<()>::new();
+    fab.register_serde_auto::<HasHP>();
+    fab.register_serde_auto::<PhysicBody>();
+    assert_eq!(
+        fab.unused_assemblers(),
+        ["has-hp", "physic-body"]
+            .into_iter()
+            .map(SmolStr::from)
+            .collect()
+    );
+
+    fab.load_str("golem { physic-body mass=1000; }", "example.kdl")
+        .unwrap_or_else(|e| panic!("{:?}", miette::Report::new(e)));
+    let golem = fab.instantiate("golem", world.spawn(), &()).unwrap();
+    assert_eq!(
+        *world.query::<&PhysicBody>(golem).unwrap(),
+        PhysicBody { mass: 1000 }
+    );
+}
+
+#[test]
+fn patching() {
+    let bp_src = r#"
+    salamander {
+        has-hp start_hp=20 {
+            resistances fire=5
+        }
+        extra-resistances fire=10 ice=-5
+    }
+    ghost {
+        extra-resistances fire=10
+    }
+    "#;
+
+    let (mut world, mut fab) = setup_both();
+    fab.register_patch(
+        "extra-resistances",
+        |hp: &mut HasHP, extra: HashMap<String, i32>| {
+            for (kind, amount) in extra {
+                *hp.resistances.entry(kind).or_default() += amount;
+            }
+        },
+    );
+    fab.load_str(bp_src, "example.kdl")
+        .unwrap_or_else(|e| panic!("{:?}", miette::Report::new(e)));
+
+    let salamander = fab.instantiate("salamander", world.spawn(), &()).unwrap();
+    let hp = world.query::<&HasHP>(salamander).unwrap();
+    assert_eq!(hp.start_hp, 20);
+    assert_eq!(
+        hp.resistances,
+        [("fire".to_owned(), 15), ("ice".to_owned(), -5)]
+            .into_iter()
+            .collect()
+    );
+    drop(hp);
+
+    // There's no HasHP to patch
+    assert!(fab.instantiate("ghost", world.spawn(), &()).is_err());
+}
+
+#[test]
+fn merge_ignores_formatting() {
+    let (mut world, mut fab) = setup_both();
+    fab.load_str("rock { physic-body mass=10; }", "rock.kdl")
+        .unwrap_or_else(|e| panic!("{:?}", miette::Report::new(e)));
+    // Same component, just written differently
+    fab.load_str(
+        r#"rock { "physic-body"   mass=0x14 /* heavier */; }"#,
+        "rock-patch.kdl",
+    )
+    .unwrap_or_else(|e| panic!("{:?}", miette::Report::new(e)));
+
+    assert_eq!(fab.resolve("rock").unwrap().components.len(), 1);
+    let rock = fab.instantiate("rock", world.spawn(), &()).unwrap();
+    assert_eq!(
+        *world.query::<&PhysicBody>(rock).unwrap(),
+        PhysicBody { mass: 20 }
+    );
+}
+
+#[test]
+fn deprecated_aliases() {
+    let bp_src = r#"
+    old-golem {
+        hp start_hp=50
+    }
+    new-golem {
+        has-hp start_hp=60
+    }
+    "#;
+
+    let (mut world, mut fab) = setup_both();
+    fab.register_deprecated_alias("hp", "has-hp");
+    fab.load_str(bp_src, "example.kdl")
+        .unwrap_or_else(|e| panic!("{:?}", miette::Report::new(e)));
+
+    let warnings = fab.take_warnings();
+    match warnings.as_slice() {
+        [LoadWarning::DeprecatedComponent(warning)] => {
+            assert_eq!(warning.blueprint, "old-golem");
+            assert_eq!(warning.component, "hp");
+            assert_eq!(warning.replacement, "has-hp");
+        }
+        _ => panic!("{:?}", warnings),
+    }
+
+    for (name, hp) in [("old-golem", 50), ("new-golem", 60)] {
+        let golem = fab.instantiate(name, world.spawn(), &()).unwrap();
+        assert_eq!(world.query::<&HasHP>(golem).unwrap().start_hp, hp);
+    }
+    assert!(!fab.unused_assemblers().contains("has-hp"));
+}
+
+#[test]
+fn required_components() {
+    let bp_src = r#"
+    mob {
+        tracked-position
+    }
+    goblin {
+        (splice)mob
+        has-hp start_hp=5
+        loot-table "goblin"
+    }
+    rock {
+        physic-body mass=10
+        tracks-position
+    }
+    "#;
+
+    let mut fab = setup_fab();
+    fab.load_str(bp_src, "example.kdl")
+        .unwrap_or_else(|e| panic!("{:?}", miette::Report::new(e)));
+
+    let names = |names: &[&str]| {
+        names
+            .iter()
+            .copied()
+            .map(SmolStr::from)
+            .collect::<BTreeSet<_>>()
+    };
+    assert_eq!(
+        fab.required_components(["goblin", "rock"]).unwrap(),
+        names(&[
+            "has-hp",
+            "loot-table",
+            "physic-body",
+            "tracked-position",
+            "tracks-position"
+        ])
+    );
+    assert_eq!(
+        fab.missing_assemblers(["goblin", "rock"]).unwrap(),
+        names(&["loot-table", "tracks-position"])
+    );
+    assert!(fab.missing_assemblers(["mob"]).unwrap().is_empty());
+    assert!(fab.required_components(["nonexistent"]).is_err());
+}